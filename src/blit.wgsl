@@ -0,0 +1,27 @@
+// Downsample one mip level into the next
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+// One triangle covering the target, with tex_coords 0..1 over it
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+@group(0) @binding(0)
+var src_tex: texture_2d<f32>;
+@group(0) @binding(1)
+var src_samp: sampler;
+
+// A linear sample between four texels averages them
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(src_tex, src_samp, in.tex_coords);
+}
@@ -3,6 +3,7 @@ use winit::event::*;
 use winit::dpi::PhysicalPosition;
 use std::time::Duration;
 use std::f32::consts::FRAC_PI_2;
+use crate::vertex::{self, Vertex};
 
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -14,11 +15,26 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
 
 const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
 
+// Mouse drags, in radians and in lengths of the orbit distance per pixel.
+const ROTATE_PER_PIXEL: f32 = 0.005;
+const PAN_PER_PIXEL: f32 = 0.0015;
+// Wheel zoom, a line being about 100 pixels.
+const ZOOM_PER_PIXEL: f32 = 0.001;
+
+// What a left drag rotates: the camera around the orbit target, or the
+// model in front of it as the arrow keys do.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Debug, Default)]
+pub enum Mode {
+    #[default]
+    Orbit,
+    ModelView,
+}
+
 #[derive(Debug)]
 pub struct Camera {
     pub position: Point3<f32>,
-    yaw: Rad<f32>,
-    pitch: Rad<f32>,
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
 }
 
 impl Camera {
@@ -38,31 +54,88 @@ impl Camera {
         }
     }
 
-    pub fn calc_matrix(&self) -> Matrix4<f32> {
+    // Unit vector the camera looks along.
+    pub fn direction(&self) -> Vector3<f32> {
         let (sin_pitch, cos_pitch) = self.pitch.0.sin_cos();
         let (sin_yaw, cos_yaw) = self.yaw.0.sin_cos();
 
+        Vector3::new(
+            cos_pitch * cos_yaw,
+            sin_pitch,
+            cos_pitch * sin_yaw
+        ).normalize()
+    }
+
+    pub fn calc_matrix(&self) -> Matrix4<f32> {
         Matrix4::look_to_rh(
             self.position,
-            Vector3::new(
-                cos_pitch * cos_yaw,
-                sin_pitch,
-                cos_pitch * sin_yaw
-            ).normalize(),
+            self.direction(),
             Vector3::unit_y(),
         )
     }
 }
 
+// The point the camera looks at and orbits, and how far away it is.
+#[derive(Debug)]
+pub struct Orbit {
+    pub target: Point3<f32>,
+    pub distance: f32,
+}
+
+impl Orbit {
+    pub fn new<T: Into<Point3<f32>>>(camera: &Camera, target: T) -> Self {
+        let target = target.into();
+        Self {
+            target,
+            distance: (target - camera.position).magnitude(),
+        }
+    }
+}
+
+// Sphere around the middle of a mesh's bounding box, to frame the mesh
+// and fit the depth range around it.
+#[derive(Copy, Clone, Debug)]
+pub struct Bounds {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl Bounds {
+    pub fn new(vertexes: &[Vertex]) -> Self {
+        let (min, max) = vertex::bounds(vertexes);
+        let center = Point3::from(min).midpoint(Point3::from(max));
+        let radius = vertexes.iter()
+            .map(|v| Point3::from(v.position).distance(center))
+            .fold(0.0, f32::max);
+        // Empty meshes and single points still get something to look at.
+        Self {
+            center: if center.x.is_finite() { center } else { Point3::origin() },
+            radius: if radius > 0.0 && radius.is_finite() { radius } else { 1.0 },
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Debug, Default)]
+pub enum ProjectionKind {
+    #[default]
+    Perspective,
+    /// Orthographic, for measuring and comparing top, front and side views
+    Ortho,
+}
+
 pub struct Projection {
+    pub kind: ProjectionKind,
     aspect: f32,
     fovy: Rad<f32>,
+    // Orthographic view height, set by zoom.
+    height: f32,
     znear: f32,
     zfar: f32,
 }
 
 impl Projection {
     pub fn new<F: Into<Rad<f32>>>(
+        kind: ProjectionKind,
         width: u32,
         height: u32,
         fovy: F,
@@ -70,26 +143,64 @@ impl Projection {
         zfar: f32,
     ) -> Self {
         Self {
+            kind,
             aspect: width as f32 / height as f32,
             fovy: fovy.into(),
+            height: 2.0,
             znear,
             zfar,
         }
     }
 
+    pub fn toggle(&mut self) {
+        self.kind = match self.kind {
+            ProjectionKind::Perspective => ProjectionKind::Ortho,
+            ProjectionKind::Ortho => ProjectionKind::Perspective,
+        };
+    }
+
+    // Orthographic views show what the perspective view shows at the
+    // orbit target, so switching keeps the target the same size.
+    pub fn zoom(&mut self, distance: f32) {
+        self.height = 2.0 * distance * (self.fovy / 2.0).tan();
+    }
+
     pub fn resize(&mut self, width: u32, height: u32) {
         self.aspect = width as f32 / height as f32;
     }
 
+    // Distance at which a sphere of radius fills the narrower of the
+    // view's height and width.
+    pub fn fit_distance(&self, radius: f32) -> f32 {
+        let half_fovx = Rad((self.aspect * (self.fovy / 2.0).tan()).atan());
+        radius / Rad::sin(if half_fovx < self.fovy / 2.0 { half_fovx } else { self.fovy / 2.0 })
+    }
+
+    // Near and far planes just outside a sphere of radius around center,
+    // with the near plane a little way off when the camera is inside it.
+    pub fn fit_depth(&mut self, camera: &Camera, center: Point3<f32>, radius: f32) {
+        let distance = camera.position.distance(center);
+        self.znear = (distance - radius).max(radius * 1e-3) * 0.9;
+        self.zfar = (distance + radius) * 1.1;
+    }
+
     pub fn calc_matrix(&self) -> Matrix4<f32> {
-        OPENGL_TO_WGPU_MATRIX * perspective(
-            self.fovy, self.aspect, self.znear, self.zfar)
+        match self.kind {
+            ProjectionKind::Perspective => OPENGL_TO_WGPU_MATRIX * perspective(
+                self.fovy, self.aspect, self.znear, self.zfar),
+            ProjectionKind::Ortho => {
+                let (h, w) = (self.height / 2.0, self.height * self.aspect / 2.0);
+                OPENGL_TO_WGPU_MATRIX * ortho(-w, w, -h, h, self.znear, self.zfar)
+            }
+        }
     }
 }
 
 pub struct ModelView {
     x_theta: Rad<f32>,
     y_theta: Rad<f32>,
+    // Point the model turns around.
+    pub center: Point3<f32>,
     // mat: Matrix4<f32>,
 }
 
@@ -98,12 +209,26 @@ impl ModelView {
         Self {
             x_theta: x_theta.into(),
             y_theta: y_theta.into(),
+            center: Point3::origin(),
         }
     }
 
     pub fn calc_matrix(&self) -> Matrix4<f32> {
-        OPENGL_TO_WGPU_MATRIX * Matrix4::from_angle_x(self.x_theta) *
-            Matrix4::from_angle_y(self.y_theta)
+        let center = self.center.to_vec();
+        OPENGL_TO_WGPU_MATRIX * Matrix4::from_translation(center) *
+            Matrix4::from_angle_x(self.x_theta) *
+            Matrix4::from_angle_y(self.y_theta) *
+            Matrix4::from_translation(-center)
+    }
+
+    // Turns the model to angle around y, for turntable frames.
+    pub fn turn(&mut self, angle: Rad<f32>) {
+        self.y_theta = angle;
+    }
+
+    // Where the center ends up, whatever the rotation.
+    pub fn world_center(&self) -> Point3<f32> {
+        self.calc_matrix().transform_point(self.center)
     }
 }
 
@@ -118,12 +243,18 @@ pub struct CameraController {
     rotate_horizontal: f32,
     rotate_vertical: f32,
     scroll: f32,
-    speed: f32,
-    sensitivity: f32,
+    pub speed: f32,
+    pub sensitivity: f32,
+    mode: Mode,
+    rotating: bool,
+    panning: bool,
+    // Mouse movement while dragging, since the last update.
+    rotate_drag: Vector2<f32>,
+    pan_drag: Vector2<f32>,
 }
 
 impl CameraController {
-    pub fn new(speed: f32, sensitivity: f32) -> Self {
+    pub fn new(speed: f32, sensitivity: f32, mode: Mode) -> Self {
         Self {
             amount_left: 0.0,
             amount_right: 0.0,
@@ -136,6 +267,11 @@ impl CameraController {
             scroll: 0.0,
             speed,
             sensitivity,
+            mode,
+            rotating: false,
+            panning: false,
+            rotate_drag: Vector2::zero(),
+            pan_drag: Vector2::zero(),
         }
     }
 
@@ -217,9 +353,25 @@ impl CameraController {
         }
     }
 
+    // Left drags rotate, middle and right drags pan.
+    pub fn process_button(&mut self, button: MouseButton, state: ElementState) -> bool {
+        let pressed = state == ElementState::Pressed;
+        match button {
+            MouseButton::Left => self.rotating = pressed,
+            MouseButton::Middle | MouseButton::Right => self.panning = pressed,
+            MouseButton::Other(_) => return false,
+        }
+        true
+    }
+
     pub fn process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
-        self.rotate_horizontal = mouse_dx as f32;
-        self.rotate_vertical = mouse_dy as f32;
+        let delta = Vector2::new(mouse_dx as f32, mouse_dy as f32);
+        if self.rotating {
+            self.rotate_drag += delta;
+        }
+        if self.panning {
+            self.pan_drag += delta;
+        }
     }
 
     pub fn process_scroll(&mut self, delta: &MouseScrollDelta) {
@@ -242,34 +394,47 @@ impl CameraController {
             += Rad(-self.rotate_vertical) * self.sensitivity * dt;
         model_view.y_theta
             += Rad(self.rotate_horizontal) * self.sensitivity * dt;
+
+        if self.mode == Mode::ModelView {
+            model_view.x_theta += Rad(self.rotate_drag.y * ROTATE_PER_PIXEL);
+            model_view.y_theta += Rad(self.rotate_drag.x * ROTATE_PER_PIXEL);
+            self.rotate_drag = Vector2::zero();
+        }
     }
 
-    pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+    // Moves the orbit target and places the camera its distance back from
+    // it along the camera's direction.
+    pub fn update_camera(&mut self, camera: &mut Camera, orbit: &mut Orbit, dt: Duration) {
         let dt = dt.as_secs_f32();
 
+        // Rotate around the target
+        if self.mode == Mode::Orbit {
+            camera.yaw += Rad(self.rotate_drag.x * ROTATE_PER_PIXEL);
+            camera.pitch += Rad(-self.rotate_drag.y * ROTATE_PER_PIXEL);
+            self.rotate_drag = Vector2::zero();
+        }
+
         // Move forward/backward and left/right
         let (yaw_sin, yaw_cos) = camera.yaw.0.sin_cos();
         let forward = Vector3::new(yaw_cos, 0.0, yaw_sin).normalize();
         let right = Vector3::new(-yaw_sin, 0.0, yaw_cos).normalize();
-        camera.position += forward * (self.amount_forward - self.amount_backward) * self.speed * dt;
-        camera.position += right * (self.amount_right - self.amount_left) * self.speed * dt;
-
-        // Move in/out (aka. "zoom")
-        // Note: this isn't an actual zoom. The camera's position
-        // changes when zooming. I've added this to make it easier
-        // to get closer to an object you want to focus on.
-        let (pitch_sin, pitch_cos) = camera.pitch.0.sin_cos();
-        let scrollward = Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin).normalize();
-        camera.position += scrollward * self.scroll * self.speed * self.sensitivity * dt;
-        self.scroll = 0.0;
+        orbit.target += forward * (self.amount_forward - self.amount_backward) * self.speed * dt;
+        orbit.target += right * (self.amount_right - self.amount_left) * self.speed * dt;
 
         // Move up/down. Since we don't use roll, we can just
         // modify the y coordinate directly.
-        camera.position.y += (self.amount_up - self.amount_down) * self.speed * dt;
+        orbit.target.y += (self.amount_up - self.amount_down) * self.speed * dt;
 
-        // Rotate
-        // camera.yaw += Rad(self.rotate_horizontal) * self.sensitivity * dt;
-        // camera.pitch += Rad(-self.rotate_vertical) * self.sensitivity * dt;
+        // Pan in the plane of the screen, keeping the point under the
+        // mouse about where it was.
+        let up = right.cross(camera.direction());
+        orbit.target += (up * self.pan_drag.y - right * self.pan_drag.x)
+            * PAN_PER_PIXEL * orbit.distance;
+        self.pan_drag = Vector2::zero();
+
+        // Zoom by moving towards or away from the target
+        orbit.distance = (orbit.distance * (self.scroll * ZOOM_PER_PIXEL).exp()).max(1e-4);
+        self.scroll = 0.0;
 
         // If process_mouse isn't called every frame, these values
         // will not get set to zero, and the camera will rotate
@@ -283,6 +448,8 @@ impl CameraController {
         } else if camera.pitch > Rad(SAFE_FRAC_PI_2) {
             camera.pitch = Rad(SAFE_FRAC_PI_2);
         }
+
+        camera.position = orbit.target - camera.direction() * orbit.distance;
     }
 }
 
@@ -1,7 +1,17 @@
 use clap::Parser;
 use clap::ValueEnum;
+use clap::CommandFactory;
+use clap::error::ErrorKind;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use crate::geometry;
 use crate::vertex;
+use crate::expr;
+use crate::export;
+use crate::colormap;
+use crate::curvature;
+use crate::texture;
+use crate::camera;
 
 #[derive(Parser,Default,Debug)]
 #[clap(author="Author Name", version, about)]
@@ -23,6 +33,153 @@ struct Cli {
     #[arg(value_enum, short, long, default_value_t=DisplayMode::Both)]
     /// Controls the way each polygon is rasterized
     display_mode: DisplayMode,
+    #[arg(value_enum, long, default_value_t = Edges::All)]
+    /// Edges drawn by the edges display modes
+    edges: Edges,
+    #[arg(long, default_value_t = 1.5)]
+    /// Width of the edges in pixels
+    edge_width: f32,
+    #[arg(long, value_name = "RRGGBB", default_value = "ffffff", value_parser = parse_color)]
+    /// Color of the edges as hex
+    edge_color: [u8; 3],
+    #[arg(long, value_name = "PATH")]
+    /// PNG or JPEG image for the texture display modes instead of the wire grid
+    texture: Option<PathBuf>,
+    #[arg(long, value_name = "S|SU,SV", default_value = "1", value_parser = parse_scale)]
+    /// Times the texture repeats across the surface
+    texture_scale: (f32, f32),
+    #[arg(value_enum, long, default_value_t = AddressMode::Repeat)]
+    /// How the texture continues past its edges
+    texture_address: AddressMode,
+    #[arg(value_enum, long, default_value_t = FilterMode::Linear)]
+    /// How the texture is filtered, within and between mip levels
+    texture_filter: FilterMode,
+    #[arg(long, default_value_t = 16, value_parser = parse_anisotropy)]
+    /// Anisotropic filtering of linear filtered textures, where supported
+    anisotropy: u8,
+    #[arg(value_enum, long, default_value_t = camera::Mode::Orbit)]
+    /// What dragging with the left mouse button rotates
+    camera: camera::Mode,
+    #[arg(value_enum, long, default_value_t = camera::ProjectionKind::Perspective)]
+    /// Starting projection, P switches between them
+    projection: camera::ProjectionKind,
+    #[arg(long, default_value_t = 1, value_parser = parse_msaa)]
+    /// Samples per pixel for anti-aliasing, 1 for none, lowered to what the
+    /// adapter supports
+    msaa: u32,
+    #[arg(long, value_name = "PATH")]
+    /// Render one frame to a PNG without opening a window
+    output: Option<PathBuf>,
+    #[arg(long, value_name = "WxH", default_value = "800x600", value_parser = parse_size)]
+    /// Size of the --output image
+    size: (u32, u32),
+    #[arg(long, requires = "output", value_parser = clap::value_parser!(u32).range(1..))]
+    /// Write this many numbered images, --output with _0000 and so on
+    /// before the extension, turning the model once around or sweeping
+    /// a parameter
+    frames: Option<u32>,
+    #[arg(long, value_name = "NAME=FROM,TO", requires = "frames", value_parser = parse_sweep,
+        allow_negative_numbers = true)]
+    /// Sweep a, b, c, sigma, frequency, radius, tube-radius, height-scale
+    /// or a --param over the frames instead of turning the model
+    sweep: Option<(String, f32, f32)>,
+    #[arg(value_enum, long, default_value_t = Easing::Linear, requires = "frames")]
+    /// How the turn or sweep speeds up and slows down over the frames
+    easing: Easing,
+    #[arg(long)]
+    /// Draw the surface z = f(x, y) instead of GEOMETRY, e.g. "sin(3*x)*cos(2*y)"
+    expr: Option<String>,
+    #[arg(long = "param", value_name = "NAME=VALUE", value_parser = parse_param)]
+    /// Named parameter usable in --expr, may be repeated
+    params: Vec<(String, f32)>,
+    #[arg(long, value_name = "PATH", conflicts_with = "expr")]
+    /// Draw a greyscale or 16-bit PNG heightmap instead of GEOMETRY
+    heightmap: Option<PathBuf>,
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["expr", "heightmap"])]
+    /// Draw a Wavefront OBJ mesh instead of GEOMETRY
+    obj: Option<PathBuf>,
+    #[arg(long, default_value_t = 0.5, allow_negative_numbers = true)]
+    /// Height of white in a heightmap, black being 0
+    height_scale: f32,
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..=1024))]
+    /// Steps in both u and v, or cells a side for implicit surfaces
+    resolution: Option<u32>,
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=1024))]
+    /// Steps in u (x for grids, around the axis for surfaces of revolution)
+    u_steps: Option<u32>,
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=1024))]
+    /// Steps in v (y for grids, along the profile for surfaces of revolution)
+    v_steps: Option<u32>,
+    #[arg(long, default_value_t = -1.0, allow_negative_numbers = true)]
+    /// Lower x bound of grids and implicit surfaces
+    xmin: f32,
+    #[arg(long, default_value_t = 1.0, allow_negative_numbers = true)]
+    /// Upper x bound of grids and implicit surfaces
+    xmax: f32,
+    #[arg(long, default_value_t = -1.0, allow_negative_numbers = true)]
+    /// Lower y bound of grids, implicit surfaces and surfaces of revolution
+    ymin: f32,
+    #[arg(long, default_value_t = 1.0, allow_negative_numbers = true)]
+    /// Upper y bound of grids, implicit surfaces and surfaces of revolution
+    ymax: f32,
+    #[arg(long, default_value_t = -1.0, allow_negative_numbers = true)]
+    /// Lower z bound of implicit surfaces
+    zmin: f32,
+    #[arg(long, default_value_t = 1.0, allow_negative_numbers = true)]
+    /// Upper z bound of implicit surfaces
+    zmax: f32,
+    #[arg(long, allow_negative_numbers = true)]
+    /// Coefficient a of the quadrics r^2 + a y^2 + 2 b y + c = 0
+    a: Option<f32>,
+    #[arg(long, allow_negative_numbers = true)]
+    /// Coefficient b of the quadrics
+    b: Option<f32>,
+    #[arg(long, allow_negative_numbers = true)]
+    /// Coefficient c of the quadrics
+    c: Option<f32>,
+    #[arg(long)]
+    /// Width of the gauss peak
+    sigma: Option<f32>,
+    #[arg(long)]
+    /// Frequency of the sinc ripples or gyroid cells
+    frequency: Option<f32>,
+    #[arg(long)]
+    /// Radius of the torus
+    radius: Option<f32>,
+    #[arg(long)]
+    /// Tube radius of the torus and trefoil
+    tube_radius: Option<f32>,
+    #[arg(long, value_name = "NAME|PATH", default_value = "rainbow")]
+    /// Color map: rainbow, viridis, magma, plasma, cividis, turbo, greyscale,
+    /// diverging, or a .csv, .gpl or image file
+    colormap: String,
+    #[arg(value_enum, long)]
+    /// Value to color by, z unless --color-expr is given
+    color_by: Option<ColorBy>,
+    #[arg(long)]
+    /// Color by f(x, y, z, u, v) with any --param, e.g. "x*y"
+    color_expr: Option<String>,
+    #[arg(long, value_name = "LO,HI", value_parser = parse_range, allow_negative_numbers = true)]
+    /// Values at the bottom and top of the color map, by default the
+    /// lowest and highest over the mesh
+    color_range: Option<(f32, f32)>,
+    #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u32).range(2..=4096))]
+    /// Texels in the color table
+    colormap_size: u32,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Write the geometry to a mesh file instead of opening a window
+    Export {
+        /// File to write
+        path: PathBuf,
+        #[arg(value_enum, long)]
+        /// File format, by default from the extension
+        format: Option<export::Format>,
+    },
 }
 
 impl Cli {
@@ -39,6 +196,186 @@ impl Cli {
             Some(Face::Back) => Some(wgpu::Face::Back)
         }
     }
+    fn options(&self) -> geometry::Options {
+        for (lo, hi, axis) in [
+            (self.xmin, self.xmax, "x"),
+            (self.ymin, self.ymax, "y"),
+            (self.zmin, self.zmax, "z"),
+        ] {
+            if lo >= hi {
+                Cli::command()
+                    .error(ErrorKind::ValueValidation, format!(
+                        "--{}min ({}) must be less than --{}max ({})",
+                        axis, lo, axis, hi))
+                    .exit();
+            }
+        }
+        geometry::Options {
+            resolution: self.resolution,
+            u_steps: self.u_steps,
+            v_steps: self.v_steps,
+            xmin: self.xmin,
+            xmax: self.xmax,
+            ymin: self.ymin,
+            ymax: self.ymax,
+            zmin: self.zmin,
+            zmax: self.zmax,
+            a: self.a,
+            b: self.b,
+            c: self.c,
+            sigma: self.sigma,
+            frequency: self.frequency,
+            radius: self.radius,
+            tube_radius: self.tube_radius,
+        }
+    }
+    fn heightmap(&self) -> Option<geometry::heightmap::Heightmap> {
+        let path = self.heightmap.as_ref()?;
+        match geometry::heightmap::Heightmap::load(path) {
+            Ok(map) => Some(map),
+            Err(error) => Cli::command()
+                .error(ErrorKind::Io, format!("{:#}", error))
+                .exit(),
+        }
+    }
+    fn texture(&self) -> Option<image::DynamicImage> {
+        let path = self.texture.as_ref()?;
+        match image::open(path) {
+            Ok(img) => Some(img),
+            Err(error) => Cli::command()
+                .error(ErrorKind::Io, format!(
+                    "loading texture {}: {}", path.display(), error))
+                .exit(),
+        }
+    }
+    fn texture_sampling(&self) -> texture::Sampling {
+        let address_mode = match self.texture_address {
+            AddressMode::Repeat => wgpu::AddressMode::Repeat,
+            AddressMode::MirrorRepeat => wgpu::AddressMode::MirrorRepeat,
+            AddressMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        };
+        let filter = match self.texture_filter {
+            FilterMode::Linear => wgpu::FilterMode::Linear,
+            FilterMode::Nearest => wgpu::FilterMode::Nearest,
+        };
+        texture::Sampling {
+            address_mode,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: filter,
+            anisotropy: self.anisotropy,
+            mipmaps: true,
+        }
+    }
+    // Name of the mesh from --obj, --heightmap or --expr, in the order
+    // make() looks for them.
+    fn loaded_name(&self) -> Option<String> {
+        let file_name = |path: &PathBuf| path.file_name()
+            .map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+        self.obj.as_ref().map(file_name)
+            .or_else(|| self.heightmap.as_ref().map(file_name))
+            .or_else(|| self.expr.as_ref().map(|src| format!("z = {}", src)))
+    }
+    fn obj(&self) -> Option<(Vec<vertex::Vertex>, Vec<u32>)> {
+        let path = self.obj.as_ref()?;
+        match geometry::obj::load(path) {
+            Ok(mesh) => Some(mesh),
+            Err(error) => Cli::command()
+                .error(ErrorKind::Io, format!("{:#}", error))
+                .exit(),
+        }
+    }
+    fn colormap(&self) -> colormap::ColorMap {
+        if let Ok(builtin) = colormap::Builtin::from_str(&self.colormap, true) {
+            return colormap::ColorMap::builtin(builtin);
+        }
+        let path = PathBuf::from(&self.colormap);
+        if path.extension().is_none() {
+            let names: Vec<_> = colormap::Builtin::value_variants().iter()
+                .filter_map(|b| b.to_possible_value())
+                .map(|v| v.get_name().to_string())
+                .collect();
+            Cli::command()
+                .error(ErrorKind::InvalidValue, format!(
+                    "unknown color map '{}', expected one of {} or a file",
+                    self.colormap, names.join(", ")))
+                .exit();
+        }
+        match colormap::ColorMap::load(&path) {
+            Ok(map) => map,
+            Err(error) => Cli::command()
+                .error(ErrorKind::Io, format!("{:#}", error))
+                .exit(),
+        }
+    }
+    fn color_by(&self) -> ColorBy {
+        match (self.color_by, &self.color_expr) {
+            (None, None) => ColorBy::Z,
+            (None, Some(_)) | (Some(ColorBy::Expr), Some(_)) => ColorBy::Expr,
+            (Some(ColorBy::Expr), None) => Cli::command()
+                .error(ErrorKind::MissingRequiredArgument,
+                    "--color-by expr needs --color-expr")
+                .exit(),
+            (Some(color_by), None) => color_by,
+            (Some(color_by), Some(_)) => Cli::command()
+                .error(ErrorKind::ArgumentConflict, format!(
+                    "--color-expr can't be used with --color-by {}",
+                    color_by.to_possible_value().unwrap().get_name()))
+                .exit(),
+        }
+    }
+    fn color_expr(&self) -> Option<ExprSurface> {
+        let src = self.color_expr.as_ref()?;
+        let mut names = COLOR_EXPR_VARS.to_vec();
+        names.extend(self.params.iter().map(|(name, _)| name.as_str()));
+        match expr::Expr::parse(src, &names) {
+            Ok(expr) => Some(ExprSurface {
+                expr,
+                params: self.params.iter().map(|(_, value)| *value).collect(),
+            }),
+            Err(error) => Cli::command()
+                .error(ErrorKind::ValueValidation,
+                    format!("invalid --color-expr {}", error.show(src)))
+                .exit(),
+        }
+    }
+    fn export(&self) -> Option<Export> {
+        let Some(Command::Export { path, format }) = &self.command else {
+            return None;
+        };
+        let format = format.or_else(|| export::Format::from_path(path))
+            .unwrap_or_else(|| Cli::command()
+                .error(ErrorKind::ValueValidation, format!(
+                    "can't tell the format of {} from its extension, use --format",
+                    path.display()))
+                .exit());
+        Some(Export { path: path.clone(), format })
+    }
+    fn frames(&self) -> Option<Frames> {
+        let count = self.frames?;
+        let sweep = self.sweep.as_ref().map(|(name, from, to)| {
+            let param = sweep_param(name, &self.params).unwrap_or_else(|message| Cli::command()
+                .error(ErrorKind::ValueValidation, message)
+                .exit());
+            Sweep { param, from: *from, to: *to }
+        });
+        Some(Frames { count, sweep, easing: self.easing })
+    }
+    fn expr(&self) -> Option<ExprSurface> {
+        let src = self.expr.as_ref()?;
+        let mut names = EXPR_VARS.to_vec();
+        names.extend(self.params.iter().map(|(name, _)| name.as_str()));
+        match expr::Expr::parse(src, &names) {
+            Ok(expr) => Some(ExprSurface {
+                expr,
+                params: self.params.iter().map(|(_, value)| *value).collect(),
+            }),
+            Err(error) => Cli::command()
+                .error(ErrorKind::ValueValidation,
+                    format!("invalid --expr {}", error.show(src)))
+                .exit(),
+        }
+    }
     // fn polygon_mode(&self) -> wgpu::PolygonMode {
     //     match self.polygon_mode {
     //         PolygonMode::Fill => wgpu::PolygonMode::Fill,
@@ -48,6 +385,120 @@ impl Cli {
     // }
 }
 
+// Variables of --expr and --color-expr, which --param names can't reuse.
+const EXPR_VARS: [&str; 2] = ["x", "y"];
+const COLOR_EXPR_VARS: [&str; 5] = ["x", "y", "z", "u", "v"];
+
+fn parse_param(s: &str) -> Result<(String, f32), String> {
+    let (name, value) = s.split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, found '{}'", s))?;
+    let name = name.trim();
+    if !expr::is_identifier(name) {
+        return Err(format!("invalid parameter name '{}', expected letters, digits \
+            and _ not starting with a digit", name));
+    }
+    if COLOR_EXPR_VARS.contains(&name) || expr::is_reserved(name) {
+        return Err(format!("parameter name '{}' is taken by a variable, constant \
+            or function", name));
+    }
+    let value = value.trim().parse()
+        .map_err(|_| format!("invalid value for '{}': '{}'", name, value))?;
+    Ok((name.to_string(), value))
+}
+
+fn check_params(params: &[(String, f32)]) -> Result<(), String> {
+    for (i, (name, _)) in params.iter().enumerate() {
+        if params[..i].iter().any(|(other, _)| other == name) {
+            return Err(format!("--param {} is given more than once", name));
+        }
+    }
+    Ok(())
+}
+
+// What --sweep NAME changes, --param names first, as long as they don't
+// shadow a geometry option.
+fn sweep_param(name: &str, params: &[(String, f32)]) -> Result<Param, String> {
+    let expr = params.iter().position(|(param, _)| param == name).map(Param::Expr);
+    let option = match name {
+        "a" => Some(Param::A),
+        "b" => Some(Param::B),
+        "c" => Some(Param::C),
+        "sigma" => Some(Param::Sigma),
+        "frequency" => Some(Param::Frequency),
+        "radius" => Some(Param::Radius),
+        "tube-radius" => Some(Param::TubeRadius),
+        "height-scale" => Some(Param::HeightScale),
+        _ => None,
+    };
+    match (expr, option) {
+        (Some(_), Some(_)) => Err(format!(
+            "can't sweep '{}', it's both a geometry option and a --param", name)),
+        (Some(param), None) | (None, Some(param)) => Ok(param),
+        (None, None) => Err(format!(
+            "can't sweep '{}', it isn't a geometry option or a --param", name)),
+    }
+}
+
+fn parse_color(s: &str) -> Result<[u8; 3], String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6)
+        .ok_or_else(|| format!("expected a hex color like ff8000, found '{}'", s))?;
+    Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+fn parse_anisotropy(s: &str) -> Result<u8, String> {
+    s.parse::<u8>().ok()
+        .filter(|a| [1, 2, 4, 8, 16].contains(a))
+        .ok_or_else(|| format!("expected 1, 2, 4, 8 or 16, found '{}'", s))
+}
+
+fn parse_msaa(s: &str) -> Result<u32, String> {
+    s.parse::<u32>().ok()
+        .filter(|n| [1, 2, 4, 8].contains(n))
+        .ok_or_else(|| format!("expected 1, 2, 4 or 8, found '{}'", s))
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let error = || format!("expected WIDTHxHEIGHT like 800x600, found '{}'", s);
+    let (w, h) = s.split_once(['x', 'X']).ok_or_else(error)?;
+    let parse = |v: &str| v.trim().parse::<u32>().ok()
+        .filter(|v| (1..=16384).contains(v))
+        .ok_or_else(error);
+    Ok((parse(w)?, parse(h)?))
+}
+
+fn parse_sweep(s: &str) -> Result<(String, f32, f32), String> {
+    let (name, range) = s.split_once('=')
+        .ok_or_else(|| format!("expected NAME=FROM,TO, found '{}'", s))?;
+    let (from, to) = range.split_once(',')
+        .ok_or_else(|| format!("expected NAME=FROM,TO, found '{}'", s))?;
+    let parse = |v: &str| v.trim().parse::<f32>()
+        .map_err(|_| format!("invalid number '{}'", v));
+    Ok((name.trim().to_string(), parse(from)?, parse(to)?))
+}
+
+fn parse_scale(s: &str) -> Result<(f32, f32), String> {
+    let parse = |v: &str| v.trim().parse::<f32>().ok()
+        .filter(|v| *v > 0.0)
+        .ok_or_else(|| format!("invalid scale '{}'", v));
+    match s.split_once(',') {
+        Some((u, v)) => Ok((parse(u)?, parse(v)?)),
+        None => parse(s).map(|s| (s, s)),
+    }
+}
+
+fn parse_range(s: &str) -> Result<(f32, f32), String> {
+    let (lo, hi) = s.split_once(',')
+        .ok_or_else(|| format!("expected LO,HI, found '{}'", s))?;
+    let parse = |v: &str| v.trim().parse::<f32>()
+        .map_err(|_| format!("invalid number '{}'", v));
+    let (lo, hi) = (parse(lo)?, parse(hi)?);
+    if lo >= hi {
+        return Err(format!("{} must be less than {}", lo, hi));
+    }
+    Ok((lo, hi))
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Default)]
 pub enum FrontFace {
     #[default]
@@ -76,15 +527,32 @@ pub enum DisplayMode {
     Colors,
     #[default]
     Both,
+    Lit,
+    Edges,
+    ColorsEdges,
 }
 
 impl DisplayMode {
-    pub fn frag_entry(&self) -> &str {
+    pub fn frag_entry(&self) -> &'static str {
         match &self {
             DisplayMode::WireFrame => "fs_wire",
             DisplayMode::Texture => "fs_texture",
             DisplayMode::Colors => "fs_colors",
             DisplayMode::Both => "fs_both",
+            DisplayMode::Lit => "fs_lit",
+            DisplayMode::Edges => "fs_edges",
+            DisplayMode::ColorsEdges => "fs_colors_edges",
+        }
+    }
+    // Whether the fragment shader needs barycentric coordinates.
+    pub fn draws_edges(&self) -> bool {
+        matches!(self, DisplayMode::Edges | DisplayMode::ColorsEdges)
+    }
+    // Edges alone blend their anti-aliased sides with the background.
+    pub fn blend(&self) -> wgpu::BlendState {
+        match &self {
+            DisplayMode::Edges => wgpu::BlendState::ALPHA_BLENDING,
+            _ => wgpu::BlendState::REPLACE,
         }
     }
     pub fn polygon_mode(&self) -> wgpu::PolygonMode {
@@ -95,6 +563,75 @@ impl DisplayMode {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Default)]
+pub enum AddressMode {
+    #[default]
+    Repeat,
+    MirrorRepeat,
+    ClampToEdge,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Default)]
+pub enum FilterMode {
+    #[default]
+    Linear,
+    Nearest,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Default)]
+pub enum Edges {
+    /// Every triangle edge
+    #[default]
+    All,
+    /// Leave out the diagonal between triangles that make a quad, the
+    /// longest edge of both
+    Quads,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Start slowly
+    EaseIn,
+    /// End slowly
+    EaseOut,
+    /// Start and end slowly
+    EaseInOut,
+}
+
+impl Easing {
+    // Eased fraction of the way through, for t from 0 to 1.
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Default)]
+pub enum ColorBy {
+    X,
+    Y,
+    #[default]
+    Z,
+    /// Distance from the origin
+    Radius,
+    /// First texture coordinate
+    U,
+    /// Second texture coordinate
+    V,
+    /// Gaussian curvature
+    Gaussian,
+    /// Mean curvature
+    Mean,
+    /// The --color-expr expression
+    Expr,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Default)]
 pub enum Geometry {
     Cube,
@@ -109,49 +646,413 @@ pub enum Geometry {
     Cone,
     Cylinder,
     Torus,
+    Mobius,
+    Klein,
+    Helicoid,
+    Trefoil,
+    Hyperboloid2,
+    Gyroid,
+    Metaballs,
 }
 
 impl Geometry {
-    pub fn make(&self) -> (Vec<vertex::Vertex>, Vec<u16>) {
+    // Quadric presets as (a, b, c) in r^2 + a y^2 + 2 b y + c = 0.
+    pub fn quadric(&self) -> Option<(f32, f32, f32)> {
+        match &self {
+            Geometry::Sphere => Some((1.0, 0.0, -1.0)),
+            Geometry::Paraboloid => Some((0.0, -0.5, 0.0)),
+            Geometry::Hyperboloid1 => Some((-1.0, 0.0, -1.0)),
+            Geometry::Cone => Some((-1.0, 0.0, 0.0)),
+            Geometry::Cylinder => Some((0.0, 0.0, -1.0)),
+            Geometry::Hyperboloid2 => Some((-1.0, 0.0, 0.2)),
+            _ => None,
+        }
+    }
+
+    pub fn make(&self, opts: &geometry::Options)
+            -> (Vec<vertex::Vertex>, Vec<u32>) {
+        // Any of the quadric coefficients --a, --b and --c override.
+        let quadric = || {
+            let (a, b, c) = self.quadric().unwrap_or_default();
+            opts.coefficients(a, b, c)
+        };
+        let hyperboloid = |(a, b, c)| geometry::surf_rot::hyperboloid(a, b, c, opts);
         match &self {
             Geometry::Cube => geometry::cube::make_cube(),
-            Geometry::Grid => geometry::grid::make_zero(),
-            Geometry::Sinc => geometry::grid::make_sinc(),
-            Geometry::Hp => geometry::grid::make_hp(),
-            Geometry::Gauss => geometry::grid::make_gauss(),
-            Geometry::Sphere =>
-                geometry::surf_rot::hyperboloid(1.0, 0.0, -1.0),
-            Geometry::Paraboloid =>
-                geometry::surf_rot::hyperboloid(0.0, -0.5, 0.0),
-            Geometry::Hyperboloid1 =>
-                geometry::surf_rot::hyperboloid(-1.0, 0.0, -1.0),
-            Geometry::Cone =>
-                geometry::surf_rot::hyperboloid(-1.0, 0.0, 0.0),
-            Geometry::Cylinder =>
-                geometry::surf_rot::hyperboloid(0.0, 0.0, -1.0),
-            Geometry::Torus => geometry::surf_rot::torus(),
+            Geometry::Grid => geometry::grid::make_zero(opts),
+            Geometry::Sinc => geometry::grid::make_sinc(opts),
+            Geometry::Hp => geometry::grid::make_hp(opts),
+            Geometry::Gauss => geometry::grid::make_gauss(opts),
+            Geometry::Sphere | Geometry::Paraboloid | Geometry::Hyperboloid1
+                | Geometry::Cone | Geometry::Cylinder => hyperboloid(quadric()),
+            Geometry::Torus => geometry::surf_rot::torus(opts),
+            Geometry::Mobius => geometry::parametric::mobius(opts),
+            Geometry::Klein => geometry::parametric::klein(opts),
+            Geometry::Helicoid => geometry::parametric::helicoid(opts),
+            Geometry::Trefoil => geometry::parametric::trefoil(opts),
+            Geometry::Hyperboloid2 => {
+                let (a, b, c) = quadric();
+                geometry::implicit::quadric(a, b, c, opts)
+            }
+            Geometry::Gyroid => geometry::implicit::gyroid(opts),
+            Geometry::Metaballs => geometry::implicit::metaballs(opts),
         }
     }
 }
 
+// Expression given with --expr or --color-expr and the values of its
+// named parameters.
+pub struct ExprSurface {
+    pub expr: expr::Expr,
+    pub params: Vec<f32>,
+}
+
+// What make() draws, the mesh from --obj, --heightmap or --expr, or a
+// preset GEOMETRY.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Source {
+    Loaded,
+    Preset(Geometry),
+}
+
+// Geometry option or --param that a sweep changes.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Param {
+    A,
+    B,
+    C,
+    Sigma,
+    Frequency,
+    Radius,
+    TubeRadius,
+    HeightScale,
+    // Index into the --param values.
+    Expr(usize),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Sweep {
+    pub param: Param,
+    pub from: f32,
+    pub to: f32,
+}
+
+// Image sequence for --frames, turning the model unless a sweep is given.
+pub struct Frames {
+    pub count: u32,
+    pub sweep: Option<Sweep>,
+    pub easing: Easing,
+}
+
+// Mesh file to write from the export command.
+pub struct Export {
+    pub path: PathBuf,
+    pub format: export::Format,
+}
+
 pub struct Args {
-    pub geometry: Geometry,
+    pub source: Source,
+    pub loaded_name: Option<String>,
+    pub expr: Option<ExprSurface>,
+    pub heightmap: Option<geometry::heightmap::Heightmap>,
+    pub obj: Option<(Vec<vertex::Vertex>, Vec<u32>)>,
+    pub height_scale: f32,
+    pub options: geometry::Options,
+    // Coefficients set for one quadric preset at runtime, which unlike
+    // --a, --b and --c leave the other presets alone.
+    pub quadrics: BTreeMap<Geometry, (f32, f32, f32)>,
+    pub export: Option<Export>,
+    pub colormap: colormap::ColorMap,
+    // Which builtin map colormap is, if it is one.
+    pub colormap_builtin: Option<colormap::Builtin>,
+    pub colormap_size: u32,
+    pub color_by: ColorBy,
+    pub color_expr: Option<ExprSurface>,
+    pub color_range: Option<(f32, f32)>,
     pub front_face: wgpu::FrontFace,
     pub cull_mode: Option<wgpu::Face>,
-    pub polygon_mode: wgpu::PolygonMode,
-    pub frag_entry: String,
+    pub display_mode: DisplayMode,
+    // Edges for the barycentric wire frame, see drawn_edges().
+    pub edges: Edges,
+    pub edge_width: f32,
+    pub edge_color: [f32; 4],
+    pub texture: Option<image::DynamicImage>,
+    pub texture_scale: (f32, f32),
+    pub texture_sampling: texture::Sampling,
+    pub msaa: u32,
+    pub camera_mode: camera::Mode,
+    pub projection: camera::ProjectionKind,
+    // Image to render offscreen instead of opening a window, and its size.
+    pub output: Option<PathBuf>,
+    pub size: (u32, u32),
+    pub frames: Option<Frames>,
 }
 
 impl Args {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         let cli = Cli::parse();
         println!("{:?}", cli);
+        Self::from_cli(cli)
+    }
+
+    fn from_cli(cli: Cli) -> Self {
+        if let Err(message) = check_params(&cli.params) {
+            Cli::command().error(ErrorKind::ArgumentConflict, message).exit();
+        }
         Self {
-            geometry: cli.geometry,
+            source: match cli.loaded_name() {
+                Some(_) => Source::Loaded,
+                None => Source::Preset(cli.geometry),
+            },
+            loaded_name: cli.loaded_name(),
+            expr: cli.expr(),
+            heightmap: cli.heightmap(),
+            obj: cli.obj(),
+            height_scale: cli.height_scale,
+            options: cli.options(),
+            quadrics: BTreeMap::new(),
+            export: cli.export(),
+            colormap: cli.colormap(),
+            colormap_builtin: colormap::Builtin::from_str(&cli.colormap, true).ok(),
+            colormap_size: cli.colormap_size,
+            color_by: cli.color_by(),
+            color_expr: cli.color_expr(),
+            color_range: cli.color_range,
             front_face: cli.front_face(),
             cull_mode: cli.cull_mode(),
-            polygon_mode: cli.display_mode.polygon_mode(),
-            frag_entry: String::from(cli.display_mode.frag_entry()),
+            display_mode: cli.display_mode,
+            edges: cli.edges,
+            edge_width: cli.edge_width,
+            edge_color: colormap::linear(cli.edge_color),
+            texture: cli.texture(),
+            texture_scale: cli.texture_scale,
+            texture_sampling: cli.texture_sampling(),
+            msaa: cli.msaa,
+            camera_mode: cli.camera,
+            projection: cli.projection,
+            output: cli.output.clone(),
+            size: cli.size,
+            frames: cli.frames(),
+        }
+    }
+
+    pub fn color_table(&self) -> image::RgbaImage {
+        self.colormap.table(self.colormap_size)
+    }
+
+    pub fn color_range(&self, vertexes: &[vertex::Vertex]) -> (f32, f32) {
+        self.color_range.unwrap_or_else(|| colormap::range(vertexes))
+    }
+
+    // Edges for the barycentric wire frame, if the display mode draws it.
+    pub fn drawn_edges(&self) -> Option<Edges> {
+        self.display_mode.draws_edges().then_some(self.edges)
+    }
+
+    // Everything make() can draw, the loaded mesh first, for switching
+    // at runtime.
+    pub fn sources(&self) -> Vec<Source> {
+        let loaded = self.loaded_name.is_some().then_some(Source::Loaded);
+        loaded.into_iter()
+            .chain(Geometry::value_variants().iter().map(|&g| Source::Preset(g)))
+            .collect()
+    }
+
+    // Moves step places through sources(), wrapping around.
+    pub fn step_source(&mut self, step: isize) {
+        let sources = self.sources();
+        let i = sources.iter().position(|&s| s == self.source).unwrap_or(0) as isize;
+        self.source = sources[(i + step).rem_euclid(sources.len() as isize) as usize];
+    }
+
+    pub fn source_name(&self) -> String {
+        self.name_of(self.source)
+    }
+
+    pub fn name_of(&self, source: Source) -> String {
+        match source {
+            Source::Loaded => self.loaded_name.clone().unwrap_or_default(),
+            Source::Preset(geometry) =>
+                geometry.to_possible_value().unwrap().get_name().to_string(),
+        }
+    }
+
+    // Changes a geometry option or --param, for the next make().
+    pub fn set_param(&mut self, param: Param, value: f32) {
+        match param {
+            Param::A => self.options.a = Some(value),
+            Param::B => self.options.b = Some(value),
+            Param::C => self.options.c = Some(value),
+            Param::Sigma => self.options.sigma = Some(value),
+            Param::Frequency => self.options.frequency = Some(value),
+            Param::Radius => self.options.radius = Some(value),
+            Param::TubeRadius => self.options.tube_radius = Some(value),
+            Param::HeightScale => self.height_scale = value,
+            Param::Expr(i) => {
+                for surface in [&mut self.expr, &mut self.color_expr].into_iter().flatten() {
+                    surface.params[i] = value;
+                }
+            }
+        }
+    }
+
+    // The mesh with its scalars filled in for coloring and its texture
+    // coordinates fitted to any --texture.
+    pub fn make(&self) -> (Vec<vertex::Vertex>, Vec<u32>) {
+        let (mut vertexes, indexes) = self.make_mesh();
+        self.fill_scalars(&mut vertexes, &indexes);
+        // OBJ texture coordinates already place the image, so they are
+        // only scaled.
+        if self.texture.is_some() {
+            if self.source == Source::Loaded && self.obj.is_some() {
+                let (su, sv) = self.texture_scale;
+                for v in vertexes.iter_mut() {
+                    v.tex_coord = [v.tex_coord[0] * su, v.tex_coord[1] * sv];
+                }
+            } else {
+                vertex::fit_tex_coords(&mut vertexes, self.texture_scale);
+            }
+        }
+        (vertexes, indexes)
+    }
+
+    fn fill_scalars(&self, vertexes: &mut [vertex::Vertex], indexes: &[u32]) {
+        let curvature = match self.color_by {
+            ColorBy::Gaussian => Some(curvature::curvature(vertexes, indexes).gaussian),
+            ColorBy::Mean => Some(curvature::curvature(vertexes, indexes).mean),
+            _ => None,
+        };
+        let mut vars = Vec::new();
+        for (i, vertex) in vertexes.iter_mut().enumerate() {
+            let [x, y, z] = vertex.position;
+            let [u, v] = vertex.tex_coord;
+            vertex.scalar = match self.color_by {
+                ColorBy::X => x,
+                ColorBy::Y => y,
+                ColorBy::Z => z,
+                ColorBy::Radius => (x * x + y * y + z * z).sqrt(),
+                ColorBy::U => u,
+                ColorBy::V => v,
+                ColorBy::Gaussian | ColorBy::Mean => curvature.as_ref().unwrap()[i],
+                ColorBy::Expr => {
+                    let surface = self.color_expr.as_ref().unwrap();
+                    vars.clear();
+                    vars.extend_from_slice(&[x, y, z, u, v]);
+                    vars.extend_from_slice(&surface.params);
+                    surface.expr.eval(&vars)
+                }
+            };
+        }
+    }
+
+    // Coefficients a quadric preset is drawn with.
+    pub fn quadric(&self, geometry: Geometry) -> Option<(f32, f32, f32)> {
+        self.quadrics.get(&geometry).copied().or_else(|| {
+            geometry.quadric().map(|(a, b, c)| self.options.coefficients(a, b, c))
+        })
+    }
+
+    fn make_mesh(&self) -> (Vec<vertex::Vertex>, Vec<u32>) {
+        if let Source::Preset(geometry) = self.source {
+            if let Some(&(a, b, c)) = self.quadrics.get(&geometry) {
+                let options = geometry::Options {
+                    a: Some(a),
+                    b: Some(b),
+                    c: Some(c),
+                    ..self.options.clone()
+                };
+                return geometry.make(&options);
+            }
+            return geometry.make(&self.options);
+        }
+        if let Some(mesh) = &self.obj {
+            return mesh.clone();
+        }
+        if let Some(map) = &self.heightmap {
+            return geometry::heightmap::make_heightmap(
+                map, self.height_scale, &self.options);
+        }
+        let surface = self.expr.as_ref().expect("loaded mesh without a source");
+        geometry::grid::make_expr(&surface.expr, &surface.params, &self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_parse() {
+        assert_eq!(parse_param("a=1.5"), Ok(("a".to_string(), 1.5)));
+        assert_eq!(parse_param(" k_2 = -3 "), Ok(("k_2".to_string(), -3.0)));
+        assert!(parse_param("a").unwrap_err().contains("expected NAME=VALUE"));
+        assert!(parse_param("a=x").unwrap_err().contains("invalid value for 'a'"));
+    }
+
+    #[test]
+    fn param_names_are_identifiers() {
+        for name in ["", "1a", "a-b", "a b", "a.b"] {
+            let err = parse_param(&format!("{}=1", name)).unwrap_err();
+            assert!(err.contains("invalid parameter name"), "{}: {}", name, err);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn param_names_arent_reserved() {
+        for name in ["x", "y", "z", "u", "v", "pi", "tau", "e", "sin", "atan2"] {
+            let err = parse_param(&format!("{}=1", name)).unwrap_err();
+            assert!(err.contains("is taken"), "{}: {}", name, err);
+        }
+    }
+
+    #[test]
+    fn params_are_given_once() {
+        let params = |names: &[&str]| -> Vec<(String, f32)> {
+            names.iter().map(|n| (n.to_string(), 1.0)).collect()
+        };
+        assert_eq!(check_params(&params(&["a", "b"])), Ok(()));
+        assert_eq!(check_params(&params(&["a", "b", "a"])),
+            Err("--param a is given more than once".to_string()));
+    }
+
+    #[test]
+    fn steps_are_bounded() {
+        let parse = |args: &[&str]| Cli::try_parse_from(
+            ["triangles_wgpu"].iter().chain(args));
+        for flag in ["--resolution", "--u-steps", "--v-steps"] {
+            let (u, v) = parse(&[flag, "1024"]).unwrap().options().steps(8, 8);
+            assert_eq!(u.max(v), 1024);
+            for value in ["0", "1025", "4294967295"] {
+                assert!(parse(&[flag, value]).is_err(), "{} {}", flag, value);
+            }
+        }
+    }
+
+    #[test]
+    fn sweeps_find_params_first() {
+        let params = vec![("k".to_string(), 1.0), ("a".to_string(), 2.0)];
+        assert_eq!(sweep_param("k", &params), Ok(Param::Expr(0)));
+        assert_eq!(sweep_param("b", &params), Ok(Param::B));
+        assert!(sweep_param("a", &params).unwrap_err().contains("both"));
+        assert!(sweep_param("q", &params).unwrap_err().contains("isn't"));
+    }
+
+    #[test]
+    fn sweeping_a_param_changes_the_mesh() {
+        let cli = Cli::try_parse_from(["triangles_wgpu", "--expr", "k*x", "--param", "k=1",
+            "--frames", "3", "--sweep", "k=0,2", "--output", "sweep.png"]).unwrap();
+        let mut args = Args::from_cli(cli);
+        let sweep = args.frames.as_ref().and_then(|frames| frames.sweep).unwrap();
+        let heights = |args: &Args| -> Vec<f32> {
+            args.make().0.iter().map(|v| v.position[2]).collect()
+        };
+        let before = heights(&args);
+        args.set_param(sweep.param, sweep.to);
+        let after = heights(&args);
+        assert!(before.iter().any(|&z| z != 0.0));
+        for (z0, z1) in before.iter().zip(&after) {
+            assert!((z1 - 2.0 * z0).abs() < 1e-5, "{} {}", z0, z1);
+        }
+    }
+}
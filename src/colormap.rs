@@ -0,0 +1,301 @@
+// Color maps from height to color, for the ctab texture the shader
+// samples and for vertex colors in exported meshes. A map is a list of
+// evenly spaced colors, linearly interpolated into a table of any size.
+use std::path::Path;
+use anyhow::*;
+use image::{RgbaImage, Rgba};
+use crate::vertex::Vertex;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Debug, Default)]
+pub enum Builtin {
+    #[default]
+    Rainbow,
+    Viridis,
+    Magma,
+    Plasma,
+    Cividis,
+    Turbo,
+    Greyscale,
+    Diverging,
+}
+
+// The perceptual maps are matplotlib's sampled at ten points, which is
+// within a shade of the full tables.
+const RAINBOW: &[u32] = &[0xff00ff, 0x0000ff, 0x00ffff, 0x00ff00, 0xffff00, 0xff0000];
+const VIRIDIS: &[u32] = &[
+    0x440154, 0x482878, 0x3e4989, 0x31688e, 0x26828e,
+    0x1f9e89, 0x35b779, 0x6ece58, 0xb5de2b, 0xfde725,
+];
+const MAGMA: &[u32] = &[
+    0x000004, 0x180f3d, 0x440f76, 0x721f81, 0x9e2f7f,
+    0xcd4071, 0xf1605d, 0xfd9668, 0xfeca8d, 0xfcfdbf,
+];
+const PLASMA: &[u32] = &[
+    0x0d0887, 0x47039f, 0x7301a8, 0x9c179e, 0xbd3786,
+    0xd8576b, 0xed7953, 0xfb9f3a, 0xfdca26, 0xf0f921,
+];
+const CIVIDIS: &[u32] = &[
+    0x00204d, 0x00336f, 0x39486b, 0x575c6d, 0x707173,
+    0x8a8779, 0xa69d75, 0xc4b56c, 0xe4cf5b, 0xffea46,
+];
+const TURBO: &[u32] = &[
+    0x30123b, 0x4662d7, 0x36aaf9, 0x1ae4b6, 0x72fe5e,
+    0xc7ef34, 0xfaba39, 0xf66b19, 0xcb2a04, 0x7a0403,
+];
+const GREYSCALE: &[u32] = &[0x000000, 0xffffff];
+// ColorBrewer RdBu, blue below the middle and red above.
+const DIVERGING: &[u32] = &[
+    0x053061, 0x2166ac, 0x4393c3, 0x92c5de, 0xd1e5f0, 0xf7f7f7,
+    0xfddbc7, 0xf4a582, 0xd6604d, 0xb2182b, 0x67001f,
+];
+
+pub struct ColorMap {
+    colors: Vec<[u8; 3]>,
+}
+
+impl ColorMap {
+    pub fn builtin(builtin: Builtin) -> Self {
+        let hex = match builtin {
+            Builtin::Rainbow => RAINBOW,
+            Builtin::Viridis => VIRIDIS,
+            Builtin::Magma => MAGMA,
+            Builtin::Plasma => PLASMA,
+            Builtin::Cividis => CIVIDIS,
+            Builtin::Turbo => TURBO,
+            Builtin::Greyscale => GREYSCALE,
+            Builtin::Diverging => DIVERGING,
+        };
+        Self {
+            colors: hex.iter()
+                .map(|&c| [(c >> 16) as u8, (c >> 8) as u8, c as u8])
+                .collect(),
+        }
+    }
+
+    // A CSV or GIMP palette of colors from low to high, or an image whose
+    // middle row (or column, bottom to top, if it is taller than wide) is
+    // the map.
+    pub fn load(path: &Path) -> Result<Self> {
+        let ext = path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        let map = match ext.as_deref() {
+            Some("csv") | Some("gpl") => {
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("reading {}", path.display()))?;
+                match ext.as_deref() {
+                    Some("csv") => Self::parse_csv(&text),
+                    _ => Self::parse_gpl(&text),
+                }
+                .with_context(|| format!("in {}", path.display()))?
+            }
+            _ => {
+                let img = image::open(path)
+                    .with_context(|| format!("loading color map {}", path.display()))?;
+                Self::from_image(&img)
+            }
+        };
+        Ok(map)
+    }
+
+    // Lines of r,g,b from 0 to 1, or 0 to 255 if any is above 1. A fourth
+    // column of alpha is ignored, as is a header line.
+    pub fn parse_csv(text: &str) -> Result<Self> {
+        let mut rows: Vec<[f32; 3]> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let values: Result<Vec<f32>, _> = fields.iter().map(|f| f.parse()).collect();
+            let values = match values {
+                Result::Ok(values) => values,
+                Err(_) if rows.is_empty() && line.chars().any(char::is_alphabetic) => continue,
+                Err(_) => bail!("line {}: invalid color '{}'", number + 1, line),
+            };
+            if values.len() != 3 && values.len() != 4 {
+                bail!("line {}: expected r,g,b or r,g,b,a, found {} values",
+                    number + 1, values.len());
+            }
+            if values.iter().any(|&v| v < 0.0) {
+                bail!("line {}: negative color '{}'", number + 1, line);
+            }
+            rows.push([values[0], values[1], values[2]]);
+        }
+        let scale = if rows.iter().flatten().any(|&v| v > 1.0) { 1.0 } else { 255.0 };
+        let colors: Vec<[u8; 3]> = rows.iter()
+            .map(|row| row.map(|v| (v * scale).round().min(255.0) as u8))
+            .collect();
+        Self::new(colors)
+    }
+
+    // GIMP palette: a "GIMP Palette" line, optional Name: and Columns:
+    // lines, then "r g b name" with r, g and b from 0 to 255.
+    pub fn parse_gpl(text: &str) -> Result<Self> {
+        let mut lines = text.lines().enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        match lines.next() {
+            Some((_, line)) if line.trim() == "GIMP Palette" => {}
+            _ => bail!("not a GIMP palette, expected \"GIMP Palette\" first"),
+        }
+        let mut colors: Vec<[u8; 3]> = Vec::new();
+        for (number, line) in lines {
+            let line = line.trim();
+            if line.starts_with('#') || line.starts_with("Name:")
+                    || line.starts_with("Columns:") {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let mut color = [0u8; 3];
+            for c in &mut color {
+                let word = words.next()
+                    .ok_or_else(|| anyhow!("line {}: expected r g b", number + 1))?;
+                *c = word.parse()
+                    .map_err(|_| anyhow!("line {}: invalid component '{}'", number + 1, word))?;
+            }
+            colors.push(color);
+        }
+        Self::new(colors)
+    }
+
+    pub fn from_image(img: &image::DynamicImage) -> Self {
+        let rgb = img.to_rgb8();
+        let (width, height) = rgb.dimensions();
+        let colors = if height > width {
+            (0..height).rev().map(|y| rgb.get_pixel(width / 2, y).0).collect()
+        } else {
+            (0..width).map(|x| rgb.get_pixel(x, height / 2).0).collect()
+        };
+        Self { colors }
+    }
+
+    fn new(colors: Vec<[u8; 3]>) -> Result<Self> {
+        if colors.is_empty() {
+            bail!("no colors found");
+        }
+        Ok(Self { colors })
+    }
+
+    // Color at t from 0 to 1.
+    pub fn at(&self, t: f32) -> [u8; 3] {
+        let last = self.colors.len() - 1;
+        let x = t.clamp(0.0, 1.0) * last as f32;
+        let i = usize::min(x.floor() as usize, last);
+        let j = usize::min(i + 1, last);
+        let f = x - i as f32;
+        let (a, b) = (self.colors[i], self.colors[j]);
+        [0, 1, 2].map(|k| (a[k] as f32 * (1.0 - f) + b[k] as f32 * f).round() as u8)
+    }
+
+    // Table of size texels, the first and last exactly the ends of the map.
+    pub fn table(&self, size: u32) -> RgbaImage {
+        let mut ctab = RgbaImage::new(size, 1);
+        let last = u32::max(size - 1, 1) as f32;
+        for x in 0..size {
+            let [r, g, b] = self.at(x as f32 / last);
+            ctab.put_pixel(x, 0, Rgba([r, g, b, 255]));
+        }
+        ctab
+    }
+}
+
+// Range of the vertex scalars, for the bottom and top of the color map.
+// Constant scalars get a unit range around their value so they show the
+// middle color.
+pub fn range(vertexes: &[Vertex]) -> (f32, f32) {
+    let lo = vertexes.iter().map(|v| v.scalar).fold(f32::INFINITY, f32::min);
+    let hi = vertexes.iter().map(|v| v.scalar).fold(f32::NEG_INFINITY, f32::max);
+    if lo >= hi {
+        let mid = if lo.is_finite() { lo } else { 0.0 };
+        return (mid - 0.5, mid + 0.5);
+    }
+    (lo, hi)
+}
+
+// sRGB color as opaque linear RGBA, the way shaders see texture colors.
+pub fn linear(rgb: [u8; 3]) -> [f32; 4] {
+    let [r, g, b] = rgb.map(|c| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    });
+    [r, g, b, 1.0]
+}
+
+// Maps a scalar in the range lo..hi to 0..1 for the color table.
+pub fn grey(scalar: f32, (lo, hi): (f32, f32)) -> f32 {
+    (scalar.clamp(lo, hi) - lo) / (hi - lo)
+}
+
+// Linear interpolation between texel centres, like the sampler on the GPU,
+// with t from 0 to 1.
+pub fn sample(ctab: &RgbaImage, t: f32) -> Rgba<u8> {
+    let last = ctab.width() - 1;
+    let x = (t.clamp(0.0, 1.0) * ctab.width() as f32 - 0.5).clamp(0.0, last as f32);
+    let x0 = x.floor() as u32;
+    let x1 = u32::min(x0 + 1, last);
+    let f = x - x0 as f32;
+    let (a, b) = (ctab.get_pixel(x0, 0).0, ctab.get_pixel(x1, 0).0);
+    Rgba([0, 1, 2, 3].map(|k| (a[k] as f32 * (1.0 - f) + b[k] as f32 * f).round() as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(result: Result<ColorMap>) -> String {
+        result.err().expect("expected an error").to_string()
+    }
+
+    #[test]
+    fn csv_in_0_to_1_or_0_to_255() {
+        let map = ColorMap::parse_csv("0,0,0\n1,0.5,0\n").unwrap();
+        assert_eq!(map.colors, [[0, 0, 0], [255, 128, 0]]);
+        let map = ColorMap::parse_csv("0, 0, 0, 255\n255, 128, 0, 255\n").unwrap();
+        assert_eq!(map.colors, [[0, 0, 0], [255, 128, 0]]);
+    }
+
+    #[test]
+    fn csv_skips_header_comments_and_blank_lines() {
+        let text = "r,g,b\n# dark\n0,0,0\n\n1,1,1 # light\n";
+        let map = ColorMap::parse_csv(text).unwrap();
+        assert_eq!(map.colors, [[0, 0, 0], [255, 255, 255]]);
+    }
+
+    #[test]
+    fn csv_errors() {
+        assert_eq!(error(ColorMap::parse_csv("0,0,0\nr,g,b\n")),
+            "line 2: invalid color 'r,g,b'");
+        assert_eq!(error(ColorMap::parse_csv("0,0\n")),
+            "line 1: expected r,g,b or r,g,b,a, found 2 values");
+        assert_eq!(error(ColorMap::parse_csv("0,-1,0\n")),
+            "line 1: negative color '0,-1,0'");
+        assert_eq!(error(ColorMap::parse_csv("r,g,b\n# none\n")), "no colors found");
+    }
+
+    #[test]
+    fn gpl_with_header_lines() {
+        let text = "\
+GIMP Palette
+Name: Two
+Columns: 2
+#
+  0   0   0\tBlack
+255 128   0\tOrange
+";
+        let map = ColorMap::parse_gpl(text).unwrap();
+        assert_eq!(map.colors, [[0, 0, 0], [255, 128, 0]]);
+    }
+
+    #[test]
+    fn gpl_errors() {
+        assert_eq!(error(ColorMap::parse_gpl("0 0 0\n")),
+            "not a GIMP palette, expected \"GIMP Palette\" first");
+        assert_eq!(error(ColorMap::parse_gpl("GIMP Palette\n0 0\n")),
+            "line 2: expected r g b");
+        assert_eq!(error(ColorMap::parse_gpl("GIMP Palette\n0 256 0\n")),
+            "line 2: invalid component '256'");
+        assert_eq!(error(ColorMap::parse_gpl("GIMP Palette\nName: Empty\n")),
+            "no colors found");
+    }
+}
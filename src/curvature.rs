@@ -0,0 +1,132 @@
+// Discrete Gaussian and mean curvature at each vertex of a triangle mesh.
+//
+// Gaussian curvature is the angle deficit, 2 pi less the angles around a
+// vertex, and mean curvature comes from the cotangent Laplacian, both
+// divided by a third of the area of the triangles around the vertex.
+// Vertexes at the same position, like those along the seams of
+// parametric surfaces, are welded first so seams don't show up as edges.
+// Vertexes on the boundary take the average of their interior neighbours.
+use std::collections::HashMap;
+use std::f32::consts;
+use cgmath::{InnerSpace, Vector3, Zero};
+use crate::vertex::Vertex;
+
+pub struct Curvature {
+    pub gaussian: Vec<f32>,
+    // Positive where the surface curves away from its normals.
+    pub mean: Vec<f32>,
+}
+
+pub fn curvature(vertexes: &[Vertex], indexes: &[u32]) -> Curvature {
+    // Welded index of each vertex, matching positions to about 1e-5.
+    let mut welded: HashMap<[i64; 3], usize> = HashMap::new();
+    let mut points: Vec<Vector3<f32>> = Vec::new();
+    let mut normals: Vec<Vector3<f32>> = Vec::new();
+    let weld: Vec<usize> = vertexes.iter().map(|v| {
+        let key = v.position.map(|x| (x as f64 * 1e5).round() as i64);
+        let i = *welded.entry(key).or_insert_with(|| {
+            points.push(v.position.into());
+            normals.push(Vector3::zero());
+            points.len() - 1
+        });
+        normals[i] += Vector3::from(v.normal);
+        i
+    }).collect();
+    let n = points.len();
+
+    let mut area = vec![0.0f32; n];
+    let mut angles = vec![0.0f32; n];
+    let mut laplacian = vec![Vector3::<f32>::zero(); n];
+    let mut edges: HashMap<(usize, usize), u32> = HashMap::new();
+
+    for tri in indexes.chunks_exact(3) {
+        let t = [tri[0], tri[1], tri[2]].map(|i| weld[i as usize]);
+        if t[0] == t[1] || t[1] == t[2] || t[2] == t[0] {
+            continue;
+        }
+        let p = t.map(|i| points[i]);
+        let face_area = (p[1] - p[0]).cross(p[2] - p[0]).magnitude() / 2.0;
+        if face_area == 0.0 {
+            continue;
+        }
+        for k in 0..3 {
+            let (i, j, l) = (k, (k + 1) % 3, (k + 2) % 3);
+            let (e1, e2) = (p[j] - p[i], p[l] - p[i]);
+            let angle = e1.angle(e2).0;
+            area[t[i]] += face_area / 3.0;
+            angles[t[i]] += angle;
+            // The angle at i weights the opposite edge from j to l.
+            let weight = 0.5 / angle.tan();
+            laplacian[t[j]] += (p[l] - p[j]) * weight;
+            laplacian[t[l]] += (p[j] - p[l]) * weight;
+            *edges.entry((t[j].min(t[l]), t[j].max(t[l]))).or_insert(0) += 1;
+        }
+    }
+
+    let mut boundary = vec![false; n];
+    let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); n];
+    for (&(a, b), &count) in &edges {
+        if count == 1 {
+            boundary[a] = true;
+            boundary[b] = true;
+        }
+        neighbours[a].push(b);
+        neighbours[b].push(a);
+    }
+
+    let interior = |i: usize| !boundary[i] && area[i] > 0.0;
+    let mut gaussian = vec![0.0f32; n];
+    let mut mean = vec![0.0f32; n];
+    for i in (0..n).filter(|&i| interior(i)) {
+        gaussian[i] = (consts::TAU - angles[i]) / area[i];
+        let normal = normals[i];
+        let normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { normal };
+        mean[i] = -0.5 * laplacian[i].dot(normal) / area[i];
+    }
+    for i in (0..n).filter(|&i| !interior(i)) {
+        let inner: Vec<usize> = neighbours[i].iter().copied()
+            .filter(|&j| interior(j))
+            .collect();
+        if !inner.is_empty() {
+            let count = inner.len() as f32;
+            gaussian[i] = inner.iter().map(|&j| gaussian[j]).sum::<f32>() / count;
+            mean[i] = inner.iter().map(|&j| mean[j]).sum::<f32>() / count;
+        }
+    }
+
+    Curvature {
+        gaussian: weld.iter().map(|&i| gaussian[i]).collect(),
+        mean: weld.iter().map(|&i| mean[i]).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{grid, surf_rot};
+
+    #[test]
+    fn sphere_curvature() {
+        let r = 2.0;
+        let pi_2 = consts::FRAC_PI_2;
+        let (vertexes, indexes) = surf_rot::surf_rot_normals(64,
+            surf_rot::arc(32, -pi_2, pi_2, r), surf_rot::arc(32, -pi_2, pi_2, 1.0), 1.0, 1.0);
+        let c = curvature(&vertexes, &indexes);
+        // Away from the poles, where the triangles thin out.
+        for (i, v) in vertexes.iter().enumerate().filter(|(_, v)| v.position[1].abs() < 0.8 * r) {
+            let at = v.position;
+            assert!((c.gaussian[i] - 1.0 / (r * r)).abs() < 0.02 / (r * r),
+                "K = {} at {:?}", c.gaussian[i], at);
+            assert!((c.mean[i] - 1.0 / r).abs() < 0.02 / r, "H = {} at {:?}", c.mean[i], at);
+        }
+    }
+
+    #[test]
+    fn plane_is_flat() {
+        let (vertexes, indexes) = grid::make_grid(8, 8, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0,
+            |x, y| 0.5 * x - 0.25 * y);
+        let c = curvature(&vertexes, &indexes);
+        assert!(c.gaussian.iter().chain(&c.mean).all(|k| k.abs() < 1e-4),
+            "{:?} {:?}", c.gaussian, c.mean);
+    }
+}
@@ -0,0 +1,251 @@
+// Write generated meshes to files for other tools, without a window or GPU.
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use anyhow::{Context, Result};
+use cgmath::{InnerSpace, Vector3};
+use image::RgbaImage;
+use crate::colormap;
+use crate::vertex::Vertex;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Debug)]
+pub enum Format {
+    /// Wavefront OBJ with texture coordinates and normals
+    Obj,
+    /// Binary STL
+    Stl,
+    /// ASCII STL
+    StlAscii,
+    /// PLY with normals, texture coordinates and height colors
+    Ply,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "obj" => Some(Format::Obj),
+            "stl" => Some(Format::Stl),
+            "ply" => Some(Format::Ply),
+            _ => None,
+        }
+    }
+}
+
+pub fn export(path: &Path, format: Format, vertexes: &[Vertex], indexes: &[u32],
+        ctab: &RgbaImage, range: (f32, f32))
+        -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("creating {}", path.display()))?;
+    let mut w = BufWriter::new(file);
+    match format {
+        Format::Obj => write_obj(&mut w, vertexes, indexes),
+        Format::Stl => write_stl(&mut w, vertexes, indexes),
+        Format::StlAscii => write_stl_ascii(&mut w, vertexes, indexes),
+        Format::Ply => write_ply(&mut w, vertexes, indexes, ctab, range),
+    }
+    .and_then(|()| w.flush())
+    .with_context(|| format!("writing {}", path.display()))
+}
+
+// Texture coordinates and normals are left out when the mesh has none,
+// rather than written as zeros.
+pub fn write_obj(w: &mut impl Write, vertexes: &[Vertex], indexes: &[u32])
+        -> std::io::Result<()> {
+    let tex_coords = vertexes.iter().any(|v| v.tex_coord != [0.0, 0.0]);
+    let normals = vertexes.iter().any(|v| v.normal != [0.0, 0.0, 0.0]);
+    writeln!(w, "# triangles_wgpu")?;
+    for v in vertexes {
+        let [x, y, z] = v.position;
+        writeln!(w, "v {} {} {}", x, y, z)?;
+    }
+    if tex_coords {
+        for v in vertexes {
+            // Back to OBJ's v = 0 at the bottom of the image.
+            let [s, t] = v.tex_coord;
+            writeln!(w, "vt {} {}", s, 1.0 - t)?;
+        }
+    }
+    if normals {
+        for v in vertexes {
+            let [x, y, z] = v.normal;
+            writeln!(w, "vn {} {} {}", x, y, z)?;
+        }
+    }
+    let corner = |i: u32| match (tex_coords, normals) {
+        (false, false) => format!("{}", i),
+        (true, false) => format!("{i}/{i}"),
+        (false, true) => format!("{i}//{i}"),
+        (true, true) => format!("{i}/{i}/{i}"),
+    };
+    for tri in indexes.chunks_exact(3) {
+        let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| corner(i + 1));
+        writeln!(w, "f {} {} {}", a, b, c)?;
+    }
+    Ok(())
+}
+
+// Facet normal from the triangle's winding.
+fn facet(vertexes: &[Vertex], tri: &[u32]) -> ([f32; 3], [[f32; 3]; 3]) {
+    let p = [tri[0], tri[1], tri[2]].map(|i| vertexes[i as usize].position);
+    let [a, b, c] = p.map(Vector3::from);
+    let n = (b - a).cross(c - a);
+    let n = if n.magnitude2() > 0.0 { n.normalize() } else { n };
+    (n.into(), p)
+}
+
+pub fn write_stl(w: &mut impl Write, vertexes: &[Vertex], indexes: &[u32])
+        -> std::io::Result<()> {
+    let mut header = [0u8; 80];
+    let name = b"triangles_wgpu";
+    header[..name.len()].copy_from_slice(name);
+    w.write_all(&header)?;
+    w.write_all(&(indexes.len() as u32 / 3).to_le_bytes())?;
+    for tri in indexes.chunks_exact(3) {
+        let (n, p) = facet(vertexes, tri);
+        for v in [n, p[0], p[1], p[2]] {
+            for x in v {
+                w.write_all(&x.to_le_bytes())?;
+            }
+        }
+        // Attribute byte count, unused
+        w.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+pub fn write_stl_ascii(w: &mut impl Write, vertexes: &[Vertex], indexes: &[u32])
+        -> std::io::Result<()> {
+    writeln!(w, "solid triangles_wgpu")?;
+    for tri in indexes.chunks_exact(3) {
+        let (n, p) = facet(vertexes, tri);
+        writeln!(w, "  facet normal {:e} {:e} {:e}", n[0], n[1], n[2])?;
+        writeln!(w, "    outer loop")?;
+        for [x, y, z] in p {
+            writeln!(w, "      vertex {:e} {:e} {:e}", x, y, z)?;
+        }
+        writeln!(w, "    endloop")?;
+        writeln!(w, "  endfacet")?;
+    }
+    writeln!(w, "endsolid triangles_wgpu")
+}
+
+// Vertex colors come from the color table the same way vs_main picks
+// them, by the vertex scalars over the color range.
+pub fn write_ply(w: &mut impl Write, vertexes: &[Vertex], indexes: &[u32],
+        ctab: &RgbaImage, range: (f32, f32))
+        -> std::io::Result<()> {
+    writeln!(w, "ply")?;
+    writeln!(w, "format ascii 1.0")?;
+    writeln!(w, "comment triangles_wgpu")?;
+    writeln!(w, "element vertex {}", vertexes.len())?;
+    for property in ["x", "y", "z", "nx", "ny", "nz", "s", "t"] {
+        writeln!(w, "property float {}", property)?;
+    }
+    for property in ["red", "green", "blue"] {
+        writeln!(w, "property uchar {}", property)?;
+    }
+    writeln!(w, "element face {}", indexes.len() / 3)?;
+    writeln!(w, "property list uchar uint vertex_indices")?;
+    writeln!(w, "end_header")?;
+    for v in vertexes {
+        let [x, y, z] = v.position;
+        let [nx, ny, nz] = v.normal;
+        let [s, t] = v.tex_coord;
+        let [r, g, b, _] = colormap::sample(ctab, colormap::grey(v.scalar, range)).0;
+        writeln!(w, "{} {} {} {} {} {} {} {} {} {} {}",
+            x, y, z, nx, ny, nz, s, t, r, g, b)?;
+    }
+    for tri in indexes.chunks_exact(3) {
+        writeln!(w, "3 {} {} {}", tri[0], tri[1], tri[2])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::obj;
+
+    // A unit square in z = 0 as two triangles.
+    fn square(normal: [f32; 3], tex: bool) -> (Vec<Vertex>, Vec<u32>) {
+        let vertexes = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]
+            .map(|[x, y]| Vertex {
+                position: [x, y, 0.0],
+                tex_coord: if tex { [x, 1.0 - y] } else { [0.0, 0.0] },
+                normal,
+                scalar: y,
+            });
+        (vertexes.to_vec(), vec![0, 1, 2, 0, 2, 3])
+    }
+
+    fn written(write: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn obj_reads_back() {
+        let (vertexes, indexes) = square([0.0, 0.0, 1.0], true);
+        let text = String::from_utf8(written(|w| write_obj(w, &vertexes, &indexes))).unwrap();
+        assert!(text.contains("f 1/1/1 2/2/2 3/3/3"));
+        let (read, read_indexes) = obj::parse(&text).unwrap();
+        assert_eq!(read_indexes, indexes);
+        for (a, b) in read.iter().zip(&vertexes) {
+            assert_eq!(a.position, b.position);
+            assert_eq!(a.normal, b.normal);
+            assert_eq!(a.tex_coord, b.tex_coord);
+        }
+    }
+
+    #[test]
+    fn obj_without_normals_or_tex_coords() {
+        let (vertexes, indexes) = square([0.0; 3], false);
+        let text = String::from_utf8(written(|w| write_obj(w, &vertexes, &indexes))).unwrap();
+        assert!(!text.contains("vn") && !text.contains("vt"));
+        assert!(text.contains("f 1 2 3\nf 1 3 4\n"));
+        let (read, read_indexes) = obj::parse(&text).unwrap();
+        assert_eq!((read.len(), read_indexes.len()), (4, 6));
+    }
+
+    #[test]
+    fn binary_stl_size() {
+        let (vertexes, indexes) = square([0.0, 0.0, 1.0], true);
+        let bytes = written(|w| write_stl(w, &vertexes, &indexes));
+        assert_eq!(bytes.len(), 84 + 50 * 2);
+        assert_eq!(bytes[80..84], 2u32.to_le_bytes());
+        // The first facet's normal, from its winding
+        let normal: Vec<f32> = bytes[84..96].chunks(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn ascii_stl_facets() {
+        let (vertexes, indexes) = square([0.0, 0.0, 1.0], true);
+        let text = String::from_utf8(written(|w| write_stl_ascii(w, &vertexes, &indexes))).unwrap();
+        assert!(text.starts_with("solid triangles_wgpu\n"));
+        assert!(text.ends_with("endsolid triangles_wgpu\n"));
+        assert_eq!(text.matches("facet normal").count(), 2);
+        assert_eq!(text.matches("vertex ").count(), 6);
+    }
+
+    #[test]
+    fn ply_counts() {
+        let (vertexes, indexes) = square([0.0, 0.0, 1.0], true);
+        let ctab = colormap::ColorMap::builtin(colormap::Builtin::Greyscale).table(16);
+        let text = String::from_utf8(
+            written(|w| write_ply(w, &vertexes, &indexes, &ctab, (0.0, 1.0)))).unwrap();
+        let (header, body) = text.split_once("end_header\n").unwrap();
+        assert!(header.contains("element vertex 4\n"));
+        assert!(header.contains("element face 2\n"));
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 4 + 2);
+        // Scalar 0 is black and 1 white in greyscale.
+        assert!(lines[0].ends_with(" 0 0 0"));
+        assert!(lines[2].ends_with(" 255 255 255"));
+        assert_eq!(lines[4], "3 0 1 2");
+    }
+}
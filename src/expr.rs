@@ -0,0 +1,509 @@
+// Expression language for z = f(x, y) surfaces given on the command line.
+//
+// Supports + - * / % ^, unary minus, parentheses, the constants pi, tau
+// and e, the functions listed in `Func`, and any variable names passed to
+// `Expr::parse`. Errors carry the 1-based column where they were found.
+use std::f32::consts;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+// Names with a meaning of their own, which variables can't take.
+pub const CONSTANTS: [&str; 3] = ["pi", "tau", "e"];
+
+pub fn is_reserved(name: &str) -> bool {
+    CONSTANTS.contains(&name) || Func::lookup(name).is_some()
+}
+
+// Whether name lexes as one identifier, so an expression can use it.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(column: usize, message: impl Into<String>) -> Self {
+        Self { column, message: message.into() }
+    }
+
+    // Source line with a caret under the offending column.
+    pub fn show(&self, src: &str) -> String {
+        format!("{}\n{}\n{:>width$} {}",
+            self, src, "^", self.message, width = self.column)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Num(f32),
+    Ident(usize, usize),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+    End,
+}
+
+struct Lexer<'a> {
+    src: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(src: &'a str) -> Self {
+        Self { src, chars: src.char_indices().peekable() }
+    }
+
+    fn column(&self, byte: usize) -> usize {
+        self.src[..byte].chars().count() + 1
+    }
+
+    // Returns the token and the column it starts at.
+    fn next_token(&mut self) -> Result<(Token, usize), ParseError> {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if !c.is_whitespace() { break }
+            self.chars.next();
+        }
+        let (start, c) = match self.chars.next() {
+            None => return Ok((Token::End, self.column(self.src.len()))),
+            Some(next) => next,
+        };
+        let column = self.column(start);
+        let token = match c {
+            '0'..='9' | '.' => {
+                let mut end = start + 1;
+                let mut exponent = false;
+                while let Some(&(i, c)) = self.chars.peek() {
+                    let sign = (c == '+' || c == '-')
+                        && matches!(self.src[..i].chars().last(), Some('e' | 'E'));
+                    if c.is_ascii_digit() || c == '.' || sign
+                        || (!exponent && (c == 'e' || c == 'E')) {
+                        exponent |= c == 'e' || c == 'E';
+                        end = i + 1;
+                        self.chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let text = &self.src[start..end];
+                Token::Num(text.parse().map_err(|_| ParseError::new(
+                    column, format!("invalid number '{}'", text)))?)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = self.chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') { break }
+                    end = i + c.len_utf8();
+                    self.chars.next();
+                }
+                Token::Ident(start, end)
+            }
+            '*' if matches!(self.chars.peek(), Some((_, '*'))) => {
+                self.chars.next();
+                Token::Op('^')
+            }
+            '+' | '-' | '*' | '/' | '%' | '^' => Token::Op(c),
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            _ => return Err(ParseError::new(
+                column, format!("unexpected character '{}'", c))),
+        };
+        Ok((token, column))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Func {
+    Sin, Cos, Tan, Asin, Acos, Atan, Atan2,
+    Sinh, Cosh, Tanh,
+    Exp, Ln, Log10, Log2, Sqrt, Cbrt, Abs, Sign,
+    Floor, Ceil, Round, Min, Max, Pow, Hypot, Sinc,
+}
+
+impl Func {
+    fn lookup(name: &str) -> Option<Self> {
+        Some(match name {
+            "sin" => Func::Sin,
+            "cos" => Func::Cos,
+            "tan" => Func::Tan,
+            "asin" => Func::Asin,
+            "acos" => Func::Acos,
+            "atan" => Func::Atan,
+            "atan2" => Func::Atan2,
+            "sinh" => Func::Sinh,
+            "cosh" => Func::Cosh,
+            "tanh" => Func::Tanh,
+            "exp" => Func::Exp,
+            "ln" | "log" => Func::Ln,
+            "log10" => Func::Log10,
+            "log2" => Func::Log2,
+            "sqrt" => Func::Sqrt,
+            "cbrt" => Func::Cbrt,
+            "abs" => Func::Abs,
+            "sign" => Func::Sign,
+            "floor" => Func::Floor,
+            "ceil" => Func::Ceil,
+            "round" => Func::Round,
+            "min" => Func::Min,
+            "max" => Func::Max,
+            "pow" => Func::Pow,
+            "hypot" => Func::Hypot,
+            "sinc" => Func::Sinc,
+            _ => return None,
+        })
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Func::Atan2 | Func::Min | Func::Max | Func::Pow | Func::Hypot => 2,
+            _ => 1,
+        }
+    }
+
+    fn apply(&self, a: f32, b: f32) -> f32 {
+        match self {
+            Func::Sin => a.sin(),
+            Func::Cos => a.cos(),
+            Func::Tan => a.tan(),
+            Func::Asin => a.asin(),
+            Func::Acos => a.acos(),
+            Func::Atan => a.atan(),
+            Func::Atan2 => a.atan2(b),
+            Func::Sinh => a.sinh(),
+            Func::Cosh => a.cosh(),
+            Func::Tanh => a.tanh(),
+            Func::Exp => a.exp(),
+            Func::Ln => a.ln(),
+            Func::Log10 => a.log10(),
+            Func::Log2 => a.log2(),
+            Func::Sqrt => a.sqrt(),
+            Func::Cbrt => a.cbrt(),
+            Func::Abs => a.abs(),
+            Func::Sign => if a == 0.0 { 0.0 } else { a.signum() },
+            Func::Floor => a.floor(),
+            Func::Ceil => a.ceil(),
+            Func::Round => a.round(),
+            Func::Min => a.min(b),
+            Func::Max => a.max(b),
+            Func::Pow => a.powf(b),
+            Func::Hypot => a.hypot(b),
+            Func::Sinc => if a == 0.0 { 1.0 } else { a.sin() / a },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Num(f32),
+    Var(usize),
+    Neg(Box<Node>),
+    Bin(char, Box<Node>, Box<Node>),
+    Call(Func, Vec<Node>),
+}
+
+impl Node {
+    fn eval(&self, vars: &[f32]) -> f32 {
+        match self {
+            Node::Num(n) => *n,
+            Node::Var(i) => vars[*i],
+            Node::Neg(a) => -a.eval(vars),
+            Node::Bin(op, a, b) => {
+                let (a, b) = (a.eval(vars), b.eval(vars));
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    '%' => a % b,
+                    _ => a.powf(b),
+                }
+            }
+            Node::Call(func, args) => {
+                let a = args[0].eval(vars);
+                let b = args.get(1).map_or(0.0, |b| b.eval(vars));
+                func.apply(a, b)
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    vars: &'a [&'a str],
+    token: Token,
+    column: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn advance(&mut self) -> Result<(), ParseError> {
+        (self.token, self.column) = self.lexer.next_token()?;
+        Ok(())
+    }
+
+    fn expect(&mut self, token: Token, what: &str) -> Result<(), ParseError> {
+        if self.token != token {
+            return Err(self.unexpected(what));
+        }
+        self.advance()
+    }
+
+    fn unexpected(&self, what: &str) -> ParseError {
+        let found = match self.token {
+            Token::End => String::from("end of expression"),
+            Token::Num(n) => format!("number {}", n),
+            Token::Ident(s, e) => format!("'{}'", &self.lexer.src[s..e]),
+            Token::Op(c) => format!("'{}'", c),
+            Token::LParen => String::from("'('"),
+            Token::RParen => String::from("')'"),
+            Token::Comma => String::from("','"),
+        };
+        ParseError::new(self.column, format!("expected {}, found {}", what, found))
+    }
+
+    // sum := product (('+' | '-') product)*
+    fn sum(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.product()?;
+        while let Token::Op(op @ ('+' | '-')) = self.token {
+            self.advance()?;
+            node = Node::Bin(op, Box::new(node), Box::new(self.product()?));
+        }
+        Ok(node)
+    }
+
+    // product := unary (('*' | '/' | '%') unary)*
+    fn product(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.unary()?;
+        while let Token::Op(op @ ('*' | '/' | '%')) = self.token {
+            self.advance()?;
+            node = Node::Bin(op, Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    // unary := ('-' | '+') unary | power
+    fn unary(&mut self) -> Result<Node, ParseError> {
+        match self.token {
+            Token::Op('-') => {
+                self.advance()?;
+                Ok(Node::Neg(Box::new(self.unary()?)))
+            }
+            Token::Op('+') => {
+                self.advance()?;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    // power := primary ('^' unary)?, so -x^2 is -(x^2) and 2^3^2 is 2^9
+    fn power(&mut self) -> Result<Node, ParseError> {
+        let base = self.primary()?;
+        if self.token == Token::Op('^') {
+            self.advance()?;
+            let exponent = self.unary()?;
+            return Ok(Node::Bin('^', Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    // primary := number | name | name '(' sum (',' sum)* ')' | '(' sum ')'
+    fn primary(&mut self) -> Result<Node, ParseError> {
+        match self.token {
+            Token::Num(n) => {
+                self.advance()?;
+                Ok(Node::Num(n))
+            }
+            Token::LParen => {
+                self.advance()?;
+                let node = self.sum()?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(node)
+            }
+            Token::Ident(start, end) => {
+                let name = &self.lexer.src[start..end];
+                let column = self.column;
+                self.advance()?;
+                if self.token == Token::LParen {
+                    let func = Func::lookup(name).ok_or_else(|| ParseError::new(
+                        column, format!("unknown function '{}'", name)))?;
+                    self.advance()?;
+                    let mut args = vec![self.sum()?];
+                    while self.token == Token::Comma {
+                        self.advance()?;
+                        args.push(self.sum()?);
+                    }
+                    self.expect(Token::RParen, "',' or ')'")?;
+                    if args.len() != func.arity() {
+                        return Err(ParseError::new(column, format!(
+                            "'{}' takes {} argument{}, found {}", name,
+                            func.arity(), if func.arity() == 1 { "" } else { "s" },
+                            args.len())));
+                    }
+                    return Ok(Node::Call(func, args));
+                }
+                if let Some(i) = self.vars.iter().position(|v| *v == name) {
+                    return Ok(Node::Var(i));
+                }
+                match name {
+                    "pi" => Ok(Node::Num(consts::PI)),
+                    "tau" => Ok(Node::Num(consts::TAU)),
+                    "e" => Ok(Node::Num(consts::E)),
+                    _ if Func::lookup(name).is_some() => Err(ParseError::new(
+                        column, format!("function '{}' needs arguments", name))),
+                    _ => Err(ParseError::new(
+                        column, format!("unknown variable '{}'", name))),
+                }
+            }
+            _ => Err(self.unexpected("a number, name or '('")),
+        }
+    }
+}
+
+// Parsed expression. Variables are looked up by position in the slice
+// of names given to `parse`, so `eval` takes their values in that order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    root: Node,
+}
+
+impl Expr {
+    pub fn parse(src: &str, vars: &[&str]) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            lexer: Lexer::new(src),
+            vars,
+            token: Token::End,
+            column: 1,
+        };
+        parser.advance()?;
+        let root = parser.sum()?;
+        if parser.token != Token::End {
+            return Err(parser.unexpected("an operator or end of expression"));
+        }
+        Ok(Self { root })
+    }
+
+    pub fn eval(&self, vars: &[f32]) -> f32 {
+        self.root.eval(vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(src: &str) -> f32 {
+        Expr::parse(src, &["x", "y"]).unwrap().eval(&[2.0, 3.0])
+    }
+
+    fn error(src: &str) -> ParseError {
+        Expr::parse(src, &["x", "y"]).unwrap_err()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("10 - 4 - 3"), 3.0);
+        assert_eq!(eval("12 / 3 / 2"), 2.0);
+        assert_eq!(eval("7 % 4 * 2"), 6.0);
+        assert_eq!(eval("2 * 3 ^ 2"), 18.0);
+        assert_eq!(eval("x * y + 1"), 7.0);
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(eval("-x"), -2.0);
+        assert_eq!(eval("--x"), 2.0);
+        assert_eq!(eval("+x"), 2.0);
+        assert_eq!(eval("-x ^ 2"), -4.0);
+        assert_eq!(eval("(-x) ^ 2"), 4.0);
+        assert_eq!(eval("x * -y"), -6.0);
+        assert_eq!(eval("2 ^ -1"), 0.5);
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_eq!(eval("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(eval("2 ** 3 ** 2"), 512.0);
+        assert_eq!(eval("(2 ^ 3) ^ 2"), 64.0);
+    }
+
+    #[test]
+    fn numbers_and_constants() {
+        assert_eq!(eval("1.5e2"), 150.0);
+        assert_eq!(eval("2.5E-1"), 0.25);
+        assert_eq!(eval(".5"), 0.5);
+        assert_eq!(eval("pi"), consts::PI);
+        assert_eq!(eval("tau"), consts::TAU);
+        assert_eq!(eval("e"), consts::E);
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(eval("sqrt(16)"), 4.0);
+        assert_eq!(eval("abs(-x)"), 2.0);
+        assert_eq!(eval("min(x, y)"), 2.0);
+        assert_eq!(eval("max(x, y)"), 3.0);
+        assert_eq!(eval("pow(x, y)"), 8.0);
+        assert_eq!(eval("hypot(3, 4)"), 5.0);
+        assert_eq!(eval("sinc(0)"), 1.0);
+        assert_eq!(eval("sign(0)"), 0.0);
+        assert_eq!(eval("log(1) + ln(1)"), 0.0);
+        assert_eq!(eval("sin(0) + cos(0)"), 1.0);
+    }
+
+    #[test]
+    fn unknown_names() {
+        let err = error("x + z");
+        assert_eq!(err.column, 5);
+        assert_eq!(err.message, "unknown variable 'z'");
+
+        let err = error("foo(x)");
+        assert_eq!(err.column, 1);
+        assert_eq!(err.message, "unknown function 'foo'");
+
+        assert_eq!(error("sin").message, "function 'sin' needs arguments");
+        assert_eq!(error("min(x)").message, "'min' takes 2 arguments, found 1");
+        assert_eq!(error("sin(x, y)").message, "'sin' takes 1 argument, found 2");
+    }
+
+    #[test]
+    fn error_columns() {
+        let err = error("x + * y");
+        assert_eq!(err.column, 5);
+        assert_eq!(err.message, "expected a number, name or '(', found '*'");
+
+        let err = error("(x + y");
+        assert_eq!(err.column, 7);
+        assert_eq!(err.message, "expected ')', found end of expression");
+
+        let err = error("x y");
+        assert_eq!(err.column, 3);
+        assert_eq!(err.message, "expected an operator or end of expression, found 'y'");
+
+        // Columns count characters, not bytes.
+        let err = Expr::parse("é + $", &["é"]).unwrap_err();
+        assert_eq!(err.column, 5);
+        assert_eq!(error("x + 1 $").column, 7);
+        assert_eq!(error("x + 1 $").message, "unexpected character '$'");
+
+        assert_eq!(error("x + 1 $").show("x + 1 $"),
+            "column 7: unexpected character '$'\nx + 1 $\n      ^ unexpected character '$'");
+    }
+}
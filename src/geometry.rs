@@ -1,5 +1,70 @@
 pub mod surf_rot;
 pub mod grid;
 pub mod cube;
+pub mod parametric;
+pub mod implicit;
+pub mod heightmap;
+pub mod obj;
 
 // put geometry from cli here?
+
+// Resolution, domain and shape parameters for the generators. Anything
+// left as None falls back to the preset's own default.
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub resolution: Option<u32>,
+    pub u_steps: Option<u32>,
+    pub v_steps: Option<u32>,
+    pub xmin: f32,
+    pub xmax: f32,
+    pub ymin: f32,
+    pub ymax: f32,
+    pub zmin: f32,
+    pub zmax: f32,
+    // Quadric coefficients in r^2 + a y^2 + 2 b y + c = 0
+    pub a: Option<f32>,
+    pub b: Option<f32>,
+    pub c: Option<f32>,
+    pub sigma: Option<f32>,
+    pub frequency: Option<f32>,
+    pub radius: Option<f32>,
+    pub tube_radius: Option<f32>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            resolution: None,
+            u_steps: None,
+            v_steps: None,
+            xmin: -1.0,
+            xmax: 1.0,
+            ymin: -1.0,
+            ymax: 1.0,
+            zmin: -1.0,
+            zmax: 1.0,
+            a: None,
+            b: None,
+            c: None,
+            sigma: None,
+            frequency: None,
+            radius: None,
+            tube_radius: None,
+        }
+    }
+}
+
+impl Options {
+    // Steps in u and v, from --u-steps/--v-steps, then --resolution, then
+    // the preset's defaults.
+    pub fn steps(&self, u: u32, v: u32) -> (u32, u32) {
+        (
+            self.u_steps.or(self.resolution).unwrap_or(u),
+            self.v_steps.or(self.resolution).unwrap_or(v),
+        )
+    }
+
+    pub fn coefficients(&self, a: f32, b: f32, c: f32) -> (f32, f32, f32) {
+        (self.a.unwrap_or(a), self.b.unwrap_or(b), self.c.unwrap_or(c))
+    }
+}
@@ -1,7 +1,8 @@
-use std::iter;
-use crate::vertex::Vertex;
+use crate::vertex::{Vertex, compute_normals};
 
-fn cube_data() -> (Vec<[i16; 3]>, Vec<[usize; 8]>, Vec<[[i16; 2]; 8]> ) {
+type CubeData = (Vec<[i16; 3]>, Vec<[usize; 8]>, Vec<[[i16; 2]; 8]>);
+
+fn cube_data() -> CubeData {
     let vertexes = [
         [-1, -1, -1], // 0
         [ 1, -1, -1], // 1
@@ -60,10 +61,12 @@ fn vert(p:[i16; 3], t:[i16; 2]) -> Vertex {
     Vertex {
         position: [p[0] as f32, p[1] as f32, p[2] as f32,],
         tex_coord: [t[0] as f32, t[1] as f32,],
+        normal: [0.0, 0.0, 0.0],
+        scalar: 0.0,
     }
 }
 
-pub fn make_cube() -> (Vec<Vertex>, Vec<u16>) {
+pub fn make_cube() -> (Vec<Vertex>, Vec<u32>) {
     let (verts, strips, tex_strips) = cube_data();
     let mut vertexes: Vec<Vertex> = Vec::new();
     for i in 0..strips.len() {
@@ -72,11 +75,11 @@ pub fn make_cube() -> (Vec<Vertex>, Vec<u16>) {
         }
     }
 
-    let mut indexes: Vec<u16> = Vec::new();
+    let mut indexes: Vec<u32> = Vec::new();
 
-    let mut j: u16 = 0;
-    for i in 0..strips.len() {
-        let k = strips[i].len() as u16 + j;
+    let mut j: u32 = 0;
+    for strip in strips.iter() {
+        let k = strip.len() as u32 + j;
         while j < k - 2 {
             indexes.push(j);
             indexes.push(j+3);
@@ -89,6 +92,9 @@ pub fn make_cube() -> (Vec<Vertex>, Vec<u16>) {
         j = k;
     }
 
+    // Corners are shared between faces, so the best we can do is average.
+    compute_normals(&mut vertexes, &indexes);
+
     (vertexes.to_vec(), indexes.to_vec())
 }
 
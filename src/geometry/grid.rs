@@ -1,14 +1,20 @@
 use std::f32::consts;
+use cgmath::{InnerSpace, Vector3};
 use crate::vertex::Vertex;
+use crate::expr::Expr;
+use super::Options;
 
+#[allow(clippy::too_many_arguments)]
 pub fn make_grid(xsize: u32, ysize: u32,
         xmin: f32, ymin: f32, xmax: f32, ymax: f32,
-        ustep: f32, vstep: f32, fz: fn(f32, f32) -> f32)
-        -> (Vec<Vertex>, Vec<u16>) {
+        ustep: f32, vstep: f32, fz: impl Fn(f32, f32) -> f32)
+        -> (Vec<Vertex>, Vec<u32>) {
 
     let xstep = (xmax - xmin) / (xsize - 1) as f32;
     let ystep = (ymax - ymin) / (ysize - 1) as f32;
     // println!("xstep = {}, ystep = {}", xstep, ystep);
+    // Normals come from the gradient of fz by central differences.
+    let h = 1e-3 * f32::max(xstep.abs(), ystep.abs());
     let mut vertexes: Vec<Vertex> = Vec::new();
     for iy in 0..ysize {
         let fy = iy as f32;
@@ -16,44 +22,62 @@ pub fn make_grid(xsize: u32, ysize: u32,
             let fx = ix as f32;
             let (x, y) = (xmin + fx * xstep, ymin + fy * ystep);
             let (u, v) = (0.0 + fx * ustep, 1.0 - fy * vstep);
+            let dzdx = (fz(x + h, y) - fz(x - h, y)) / (2.0 * h);
+            let dzdy = (fz(x, y + h) - fz(x, y - h)) / (2.0 * h);
+            let normal = Vector3::new(-dzdx, -dzdy, 1.0).normalize();
             vertexes.push(Vertex {
                 position: [x, y, fz(x, y)],
-                tex_coord: [u, v]
+                tex_coord: [u, v],
+                normal: normal.into(),
+                scalar: 0.0,
             });
         }
     }
 
-    let mut indexes: Vec<u16> = Vec::new();
+    let mut indexes: Vec<u32> = Vec::new();
 
     for iy in 0..ysize - 1 {
         let y = iy * xsize;
         let y1 = (iy + 1) * xsize;
         for ix in 0..xsize - 1 {
-            indexes.push((ix + y) as u16);
-            indexes.push(((ix + 1) + y1) as u16);
-            indexes.push((ix + y1) as u16);
-            indexes.push((ix + y) as u16);
-            indexes.push(((ix + 1) + y) as u16);
-            indexes.push(((ix + 1) + y1) as u16);
+            indexes.push(ix + y);
+            indexes.push((ix + 1) + y1);
+            indexes.push(ix + y1);
+            indexes.push(ix + y);
+            indexes.push((ix + 1) + y);
+            indexes.push((ix + 1) + y1);
         }
     }
 
     (vertexes.to_vec(), indexes.to_vec())
 }
 
-pub fn make_zero() -> (Vec<Vertex>, Vec<u16>) {
+// Grid over the --xmin/--xmax/--ymin/--ymax domain with xsteps by ysteps
+// quads unless the options say otherwise.
+pub fn make_domain(opts: &Options, xsteps: u32, ysteps: u32,
+        fz: impl Fn(f32, f32) -> f32)
+        -> (Vec<Vertex>, Vec<u32>) {
+    let (xsteps, ysteps) = opts.steps(xsteps, ysteps);
+    make_grid(
+        xsteps + 1, ysteps + 1,
+        opts.xmin, opts.ymin, opts.xmax, opts.ymax,
+        1.0, 1.0,
+        fz)
+}
+
+pub fn make_zero(opts: &Options) -> (Vec<Vertex>, Vec<u32>) {
     fn zero(_x: f32, _y: f32) -> f32 { 0.0 }
-    make_grid(11, 11, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0, zero)
+    make_domain(opts, 10, 10, zero)
 }
 
-pub fn sinc(x: f32, y: f32) -> f32 {
-    let scale = 2.0;
+pub fn sinc(scale: f32, x: f32, y: f32) -> f32 {
     let d = scale * consts::PI * f32::sqrt(x * x + y * y);
     if d == 0.0 { 1.0 } else { f32::sin(d) / d }
 }
 
-pub fn make_sinc() -> (Vec<Vertex>, Vec<u16>) {
-    make_grid(21, 21, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0, sinc)
+pub fn make_sinc(opts: &Options) -> (Vec<Vertex>, Vec<u32>) {
+    let scale = opts.frequency.unwrap_or(2.0);
+    make_domain(opts, 20, 20, |x, y| sinc(scale, x, y))
 }
 
 pub fn gauss(sigma: f32, mu: f32, x: f32, y: f32) -> f32 {
@@ -62,26 +86,58 @@ pub fn gauss(sigma: f32, mu: f32, x: f32, y: f32) -> f32 {
         / (sigma * f32::sqrt(2. * consts::PI))
 }
 
-fn gausser(x: f32, y: f32) -> f32 {
-    gauss(0.3, 0.0, x, y)
-}
-
-pub fn make_gauss() -> (Vec<Vertex>, Vec<u16>) {
-    make_grid(
-        21, 21,
-        -1.0, -1.0, 1.0, 1.0,
-        1.0, 1.0,
-        gausser)
+pub fn make_gauss(opts: &Options) -> (Vec<Vertex>, Vec<u32>) {
+    let sigma = opts.sigma.unwrap_or(0.3);
+    make_domain(opts, 20, 20, |x, y| gauss(sigma, 0.0, x, y))
 }
 
 pub fn hyperbolic_paraboloid(x: f32, y: f32) -> f32 {
     x * x - y * y
 }
 
-pub fn make_hp() -> (Vec<Vertex>, Vec<u16>) {
-    make_grid(
-        21, 21,
-        -1.0, -1.0, 1.0, 1.0,
-        1.0, 1.0,
-        hyperbolic_paraboloid)
+pub fn make_hp(opts: &Options) -> (Vec<Vertex>, Vec<u32>) {
+    make_domain(opts, 20, 20, hyperbolic_paraboloid)
+}
+
+// Surface from a parsed --expr, with x and y as the first two variables
+// followed by the named parameters in order.
+pub fn make_expr(expr: &Expr, params: &[f32], opts: &Options)
+        -> (Vec<Vertex>, Vec<u32>) {
+    make_domain(opts, 40, 40, |x, y| {
+        let mut vars = vec![x, y];
+        vars.extend_from_slice(params);
+        expr.eval(&vars)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex::index_data;
+
+    #[test]
+    fn large_grid_indexes_are_valid() {
+        // 300 x 300 = 90,000 vertexes, past what u16 indexes can reach.
+        let (vertexes, indexes) = make_grid(
+            300, 300, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0, hyperbolic_paraboloid);
+        assert_eq!(vertexes.len(), 90_000);
+        assert_eq!(indexes.len(), 299 * 299 * 6);
+        assert!(indexes.iter().all(|&i| (i as usize) < vertexes.len()));
+        assert_eq!(indexes.iter().max(), Some(&89_999));
+
+        let (bytes, format) = index_data(&indexes);
+        assert_eq!(format, wgpu::IndexFormat::Uint32);
+        assert_eq!(bytes.len(), indexes.len() * 4);
+    }
+
+    #[test]
+    fn small_grid_uses_u16_indexes() {
+        let (vertexes, indexes) = make_sinc(&Options::default());
+        let (bytes, format) = index_data(&indexes);
+        assert_eq!(format, wgpu::IndexFormat::Uint16);
+        assert_eq!(bytes.len(), indexes.len() * 2);
+        let narrow = bytes.chunks(2).map(|b| u16::from_ne_bytes([b[0], b[1]]));
+        assert!(narrow.zip(&indexes).all(|(n, &i)| n as u32 == i));
+        assert!(indexes.iter().all(|&i| (i as usize) < vertexes.len()));
+    }
 }
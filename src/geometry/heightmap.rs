@@ -0,0 +1,120 @@
+use std::path::Path;
+use anyhow::*;
+use crate::vertex::Vertex;
+use super::Options;
+
+// Greyscale image as heights from 0 to 1. Colour images use their luma
+// and 8-bit images are widened, so 16-bit PNGs keep their full precision.
+pub struct Heightmap {
+    pub width: u32,
+    pub height: u32,
+    heights: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn load(path: &Path) -> Result<Self> {
+        let img = image::open(path)
+            .with_context(|| format!("loading heightmap {}", path.display()))?;
+        Ok(Self::from_image(&img))
+    }
+
+    pub fn from_image(img: &image::DynamicImage) -> Self {
+        let luma = img.to_luma16();
+        Self {
+            width: luma.width(),
+            height: luma.height(),
+            heights: luma.pixels()
+                .map(|p| p.0[0] as f32 / u16::MAX as f32)
+                .collect(),
+        }
+    }
+
+    fn pixel(&self, x: u32, y: u32) -> f32 {
+        self.heights[(y * self.width + x) as usize]
+    }
+
+    // Bilinear sample with s across and t down the image, both 0 to 1.
+    pub fn sample(&self, s: f32, t: f32) -> f32 {
+        let x = s.clamp(0.0, 1.0) * (self.width - 1) as f32;
+        let y = t.clamp(0.0, 1.0) * (self.height - 1) as f32;
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = (
+            u32::min(x0 + 1, self.width - 1),
+            u32::min(y0 + 1, self.height - 1),
+        );
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let top = self.pixel(x0, y0) * (1.0 - fx) + self.pixel(x1, y0) * fx;
+        let bottom = self.pixel(x0, y1) * (1.0 - fx) + self.pixel(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+// Grid over the x and y bounds with heights from 0 to scale. There is one
+// vertex per pixel unless --resolution or --u-steps/--v-steps ask for a
+// coarser (or finer) grid, which is then sampled bilinearly. The top row
+// of the image lands at ymax.
+pub fn make_heightmap(map: &Heightmap, scale: f32, opts: &Options)
+        -> (Vec<Vertex>, Vec<u32>) {
+    let (xmin, xmax, ymin, ymax) = (opts.xmin, opts.xmax, opts.ymin, opts.ymax);
+    super::grid::make_domain(
+        opts,
+        u32::max(map.width - 1, 1), u32::max(map.height - 1, 1),
+        |x, y| scale * map.sample((x - xmin) / (xmax - xmin), (ymax - y) / (ymax - ymin)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, GrayImage, Luma};
+
+    fn heightmap(width: u32, height: u32, f: impl Fn(u32, u32) -> u8) -> Heightmap {
+        let img = GrayImage::from_fn(width, height, |x, y| Luma([f(x, y)]));
+        Heightmap::from_image(&DynamicImage::ImageLuma8(img))
+    }
+
+    #[test]
+    fn sample_interpolates_pixels() {
+        let map = heightmap(2, 2, |x, y| if (x, y) == (1, 0) { 255 } else { 0 });
+        assert_eq!(map.sample(0.0, 0.0), 0.0);
+        assert_eq!(map.sample(1.0, 0.0), 1.0);
+        assert_eq!(map.sample(0.5, 0.0), 0.5);
+        assert_eq!(map.sample(0.5, 0.5), 0.25);
+        // Outside the image clamps to its edge
+        assert_eq!(map.sample(2.0, -1.0), 1.0);
+    }
+
+    #[test]
+    fn heights_follow_the_scale() {
+        // White on the top row, which lands at ymax
+        let map = heightmap(3, 2, |_, y| if y == 0 { 255 } else { 0 });
+        let opts = Options::default();
+        let (vertexes, indexes) = make_heightmap(&map, 0.75, &opts);
+        assert_eq!(vertexes.len(), 6);
+        assert_eq!(indexes.len(), 2 * 2 * 3);
+        for v in &vertexes {
+            let expected = if v.position[1] == opts.ymax { 0.75 } else { 0.0 };
+            assert!((v.position[2] - expected).abs() < 1e-6, "{:?}", v.position);
+        }
+    }
+
+    #[test]
+    fn flat_image_faces_up() {
+        let map = heightmap(4, 4, |_, _| 128);
+        let (vertexes, _) = make_heightmap(&map, 1.0, &Options::default());
+        for v in &vertexes {
+            assert!((v.position[2] - 128.0 / 255.0).abs() < 1e-4);
+            assert_eq!(v.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn single_row_or_column() {
+        for (width, height) in [(1, 5), (5, 1), (1, 1)] {
+            let map = heightmap(width, height, |x, y| (x + y) as u8 * 10);
+            let (vertexes, indexes) = make_heightmap(&map, 1.0, &Options::default());
+            assert!(!indexes.is_empty());
+            assert!(indexes.iter().all(|&i| (i as usize) < vertexes.len()));
+            assert!(vertexes.iter().all(|v| v.position.iter().all(|p| p.is_finite())));
+        }
+    }
+}
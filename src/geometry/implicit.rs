@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::f32::consts;
+use cgmath::{InnerSpace, Vector3};
+use crate::vertex::Vertex;
+use super::Options;
+
+// Marching cubes over the box [min, max] split into resolution^3 cells,
+// giving the surface f(x, y, z) = 0 with f < 0 taken as inside.
+//
+// Instead of the usual 256 entry tables typed in by hand, the polygons
+// for each corner configuration are worked out once from the faces of
+// the cube: each face contributes the segments where the surface crosses
+// it and the segments chain into closed polygons. Faces with two
+// diagonal inside corners always cut those corners off, and since
+// neighbouring cells see the same face the same way the mesh has no
+// cracks. Polygons are fanned from a vertex whose diagonals stay off the
+// cell's faces, so no edge is shared with the next cell's triangles.
+
+// Corner i of a cell is offset by bit 0 in x, bit 1 in y and bit 2 in z.
+fn corner(i: usize) -> [u32; 3] {
+    [(i & 1) as u32, (i >> 1 & 1) as u32, (i >> 2 & 1) as u32]
+}
+
+// Corners of each face, counter-clockwise seen from outside the cell.
+const FACES: [[usize; 4]; 6] = [
+    [0, 4, 6, 2], // -x
+    [1, 3, 7, 5], // +x
+    [0, 1, 5, 4], // -y
+    [2, 6, 7, 3], // +y
+    [0, 2, 3, 1], // -z
+    [4, 5, 7, 6], // +z
+];
+
+// Cell edge as its pair of corners, smallest first.
+type Edge = (usize, usize);
+
+fn edge(a: usize, b: usize) -> Edge {
+    (a.min(b), a.max(b))
+}
+
+// Whether two cell edges lie on the same face of the cell.
+fn same_face(e: Edge, f: Edge) -> bool {
+    FACES.iter().any(|face| [e.0, e.1, f.0, f.1].iter().all(|c| face.contains(c)))
+}
+
+// Polygons, as cycles of cell edges, for one configuration of inside
+// corners (bit i set when corner i is inside). Each is rotated to start
+// at a vertex it can be fanned from.
+fn polygons(case: usize) -> Vec<Vec<Edge>> {
+    let inside = |i: usize| case >> i & 1 == 1;
+
+    // Segments run from where the boundary of a face goes from inside to
+    // outside to where it comes back in, so each crossed edge starts one
+    // segment and ends another.
+    let mut next: HashMap<Edge, Edge> = HashMap::new();
+    for face in FACES {
+        let crossing = |k: usize| {
+            let (a, b) = (face[k], face[(k + 1) % 4]);
+            (inside(a) != inside(b)).then(|| (inside(a), edge(a, b)))
+        };
+        for k in 0..4 {
+            if let Some((true, start)) = crossing(k) {
+                // Walk back to the nearest crossing coming in, which cuts
+                // off the inside corners when a face is ambiguous.
+                let end = (1..4)
+                    .find_map(|back| match crossing((k + 4 - back) % 4) {
+                        Some((false, end)) => Some(end),
+                        _ => None,
+                    })
+                    .unwrap();
+                next.insert(start, end);
+            }
+        }
+    }
+
+    let mut polygons = Vec::new();
+    while let Some(&first) = next.keys().min() {
+        let mut polygon = vec![first];
+        let mut at = next.remove(&first).unwrap();
+        while at != first {
+            polygon.push(at);
+            at = next.remove(&at).unwrap();
+        }
+        let n = polygon.len();
+        // Every one of the 256 cases has such a vertex.
+        let fan = (0..n).find(|&k| {
+            (2..n - 1).all(|j| !same_face(polygon[k], polygon[(k + j) % n]))
+        }).unwrap();
+        polygon.rotate_left(fan);
+        polygons.push(polygon);
+    }
+    polygons
+}
+
+pub fn make_implicit(min: [f32; 3], max: [f32; 3], resolution: u32,
+        f: impl Fn(f32, f32, f32) -> f32)
+        -> (Vec<Vertex>, Vec<u32>) {
+
+    let table: Vec<_> = (0..256).map(polygons).collect();
+
+    let n = resolution;
+    let step = [0, 1, 2].map(|k| (max[k] - min[k]) / n as f32);
+    let point = |p: [u32; 3]| [0, 1, 2].map(|k| min[k] + p[k] as f32 * step[k]);
+
+    // Sample the field once at every lattice point.
+    // In usize, as (n + 1)^3 outgrows u32 from n = 1625.
+    let side = n as usize + 1;
+    let lattice = |p: [u32; 3]| (p[2] as usize * side + p[1] as usize) * side + p[0] as usize;
+    let mut values = vec![0.0; side * side * side];
+    for z in 0..=n {
+        for y in 0..=n {
+            for x in 0..=n {
+                let [px, py, pz] = point([x, y, z]);
+                values[lattice([x, y, z])] = f(px, py, pz);
+            }
+        }
+    }
+
+    // Normals from the gradient of f by central differences.
+    let h = 1e-3 * step.iter().copied().fold(0.0, f32::max);
+    let normal = |p: [f32; 3]| {
+        let [x, y, z] = p;
+        let g = Vector3::new(
+            f(x + h, y, z) - f(x - h, y, z),
+            f(x, y + h, z) - f(x, y - h, z),
+            f(x, y, z + h) - f(x, y, z - h));
+        if g.magnitude2() > 0.0 { g.normalize() } else { g }
+    };
+
+    let mut vertexes: Vec<Vertex> = Vec::new();
+    let mut indexes: Vec<u32> = Vec::new();
+    // Vertexes are shared between cells by the lattice edge they lie on.
+    let mut welded: HashMap<(usize, usize), u32> = HashMap::new();
+
+    for z in 0..n {
+        for y in 0..n {
+            for x in 0..n {
+                let at = |i: usize| {
+                    let c = corner(i);
+                    [x + c[0], y + c[1], z + c[2]]
+                };
+                let case = (0..8)
+                    .filter(|&i| values[lattice(at(i))] < 0.0)
+                    .fold(0, |case, i| case | 1 << i);
+                for polygon in &table[case] {
+                    let ring: Vec<u32> = polygon.iter().map(|&(a, b)| {
+                        let (pa, pb) = (at(a), at(b));
+                        let key = (lattice(pa), lattice(pb));
+                        *welded.entry(key).or_insert_with(|| {
+                            let (fa, fb) = (values[key.0], values[key.1]);
+                            let t = fa / (fa - fb);
+                            let (qa, qb) = (point(pa), point(pb));
+                            let position = [0, 1, 2]
+                                .map(|k| qa[k] + t * (qb[k] - qa[k]));
+                            vertexes.push(Vertex {
+                                position,
+                                tex_coord: [
+                                    (position[0] - min[0]) / step[0],
+                                    (position[1] - min[1]) / step[1],
+                                ],
+                                normal: normal(position).into(),
+                                scalar: 0.0,
+                            });
+                            vertexes.len() as u32 - 1
+                        })
+                    }).collect();
+                    // Polygons wind clockwise seen from outside, so the
+                    // triangles are reversed to face along the gradient.
+                    for k in 1..ring.len() - 1 {
+                        indexes.extend([ring[0], ring[k + 1], ring[k]]);
+                    }
+                }
+            }
+        }
+    }
+
+    (vertexes, indexes)
+}
+
+// Presets sample the --xmin..--zmax box with --resolution cells a side.
+fn make_box(opts: &Options, resolution: u32, f: impl Fn(f32, f32, f32) -> f32)
+        -> (Vec<Vertex>, Vec<u32>) {
+    make_implicit(
+        [opts.xmin, opts.ymin, opts.zmin],
+        [opts.xmax, opts.ymax, opts.zmax],
+        opts.resolution.unwrap_or(resolution),
+        f)
+}
+
+/// r^2 + a y^2 + 2 b y + c = 0, the same family as surf_rot::hyperboloid
+/// but without holes where the profile isn't defined.
+pub fn quadric(a: f32, b: f32, c: f32, opts: &Options)
+        -> (Vec<Vertex>, Vec<u32>) {
+    make_box(opts, 24, |x, y, z| {
+        x * x + z * z + a * y * y + 2.0 * b * y + c
+    })
+}
+
+pub fn gyroid(opts: &Options) -> (Vec<Vertex>, Vec<u32>) {
+    let s = consts::PI * opts.frequency.unwrap_or(1.0);
+    make_box(opts, 32, |x, y, z| {
+        let (x, y, z) = (s * x, s * y, s * z);
+        f32::sin(x) * f32::cos(y) + f32::sin(y) * f32::cos(z)
+            + f32::sin(z) * f32::cos(x)
+    })
+}
+
+pub fn metaballs(opts: &Options) -> (Vec<Vertex>, Vec<u32>) {
+    let balls: [([f32; 3], f32); 3] = [
+        ([-0.35, -0.2, 0.0], 0.35),
+        ([0.35, -0.2, 0.0], 0.3),
+        ([0.0, 0.35, 0.1], 0.3),
+    ];
+    make_box(opts, 32, |x, y, z| {
+        let sum: f32 = balls.iter().map(|&([cx, cy, cz], r)| {
+            let d2 = (x - cx).powi(2) + (y - cy).powi(2) + (z - cz).powi(2);
+            r * r / d2.max(1e-6)
+        }).sum();
+        1.0 - sum
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cell edges joining an inside corner to an outside one.
+    fn crossed(case: usize) -> Vec<Edge> {
+        let mut edges: Vec<Edge> = FACES.iter()
+            .flat_map(|face| (0..4).map(move |k| edge(face[k], face[(k + 1) % 4])))
+            .filter(|&(a, b)| (case >> a & 1) != (case >> b & 1))
+            .collect();
+        edges.sort();
+        edges.dedup();
+        edges
+    }
+
+    #[test]
+    fn every_case_has_polygons() {
+        for case in 0..256 {
+            let polygons = polygons(case);
+            let mut used: Vec<Edge> = polygons.iter().flatten().copied().collect();
+            used.sort();
+            // Each crossed edge gets one vertex, in one polygon.
+            assert_eq!(used, crossed(case), "case {:08b}", case);
+            for polygon in &polygons {
+                let n = polygon.len();
+                assert!(n >= 3, "case {:08b} has a {}-gon", case, n);
+                // The fan's diagonals stay inside the cell.
+                for j in 2..n - 1 {
+                    assert!(!same_face(polygon[0], polygon[j]), "case {:08b}", case);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sphere_is_closed() {
+        let (vertexes, indexes) = make_implicit([-1.0; 3], [1.0; 3], 12,
+            |x, y, z| x * x + y * y + z * z - 0.7);
+        assert!(!indexes.is_empty());
+        assert!(indexes.iter().all(|&i| (i as usize) < vertexes.len()));
+        // Every edge is shared by exactly two triangles, running opposite
+        // ways in each so they face the same side.
+        let mut edges: HashMap<(u32, u32), (usize, i32)> = HashMap::new();
+        for tri in indexes.chunks_exact(3) {
+            assert!(tri[0] != tri[1] && tri[1] != tri[2] && tri[2] != tri[0]);
+            for k in 0..3 {
+                let (a, b) = (tri[k], tri[(k + 1) % 3]);
+                let (count, direction) = edges.entry((a.min(b), a.max(b))).or_insert((0, 0));
+                *count += 1;
+                *direction += if a < b { 1 } else { -1 };
+            }
+        }
+        assert!(edges.values().all(|&edge| edge == (2, 0)));
+        // Closed surfaces of genus 0 have V - E + F = 2.
+        let (v, e, f) = (vertexes.len(), edges.len(), indexes.len() / 3);
+        assert_eq!(v + f - e, 2);
+    }
+}
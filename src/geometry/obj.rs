@@ -0,0 +1,229 @@
+// Wavefront OBJ import. Reads positions, texture coordinates, normals and
+// polygon faces, which are triangulated as fans. Face vertexes without a
+// normal get one averaged from the faces around them. Groups, materials,
+// smoothing and other statements are skipped.
+use std::collections::HashMap;
+use std::path::Path;
+use anyhow::*;
+use crate::vertex::{Vertex, compute_normals};
+
+pub fn load(path: &Path) -> Result<(Vec<Vertex>, Vec<u32>)> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("reading {}", path.display()))?;
+    parse(&text).with_context(|| format!("in {}", path.display()))
+}
+
+pub fn parse(text: &str) -> Result<(Vec<Vertex>, Vec<u32>)> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut tex_coords: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+
+    let mut vertexes: Vec<Vertex> = Vec::new();
+    let mut indexes: Vec<u32> = Vec::new();
+    // Each distinct v/vt/vn triple becomes one vertex.
+    let mut seen: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+    // Whether each vertex came without a normal.
+    let mut missing: Vec<bool> = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let at = || format!("line {}", number + 1);
+        match keyword {
+            "v" => {
+                positions.push(floats(words, 3).with_context(at)?);
+            }
+            "vt" => {
+                let [u, v, _] = floats(words, 1).with_context(at)?;
+                // OBJ puts v = 0 at the bottom of the image, wgpu at the top.
+                tex_coords.push([u, 1.0 - v]);
+            }
+            "vn" => {
+                normals.push(floats(words, 3).with_context(at)?);
+            }
+            "f" => {
+                let mut face: Vec<u32> = Vec::new();
+                for word in words {
+                    let mut refs = word.split('/');
+                    let v = index(refs.next(), positions.len(), "position")
+                        .with_context(at)?
+                        .ok_or_else(|| anyhow!("{}: face vertex '{}' has no position", at(), word))?;
+                    let vt = index(refs.next(), tex_coords.len(), "texture coordinate")
+                        .with_context(at)?;
+                    let vn = index(refs.next(), normals.len(), "normal")
+                        .with_context(at)?;
+                    let i = *seen.entry((v, vt, vn)).or_insert_with(|| {
+                        vertexes.push(Vertex {
+                            position: positions[v],
+                            tex_coord: vt.map_or([0.0, 0.0], |vt| tex_coords[vt]),
+                            normal: vn.map_or([0.0, 0.0, 0.0], |vn| normals[vn]),
+                            scalar: 0.0,
+                        });
+                        missing.push(vn.is_none());
+                        vertexes.len() as u32 - 1
+                    });
+                    face.push(i);
+                }
+                if face.len() < 3 {
+                    bail!("{}: face needs at least 3 vertexes, found {}", at(), face.len());
+                }
+                for k in 1..face.len() - 1 {
+                    indexes.extend([face[0], face[k], face[k + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if indexes.is_empty() {
+        bail!("no faces found");
+    }
+    if missing.contains(&true) {
+        let mut computed = vertexes.clone();
+        compute_normals(&mut computed, &indexes);
+        for ((vertex, computed), missing) in vertexes.iter_mut().zip(computed).zip(missing) {
+            if missing {
+                vertex.normal = computed.normal;
+            }
+        }
+    }
+    Ok((vertexes, indexes))
+}
+
+// The rest of a line as up to 3 numbers, needing at least `min` of them
+// and filling the others with 0. Any beyond 3, like w, are ignored.
+fn floats<'a>(words: impl Iterator<Item = &'a str>, min: usize) -> Result<[f32; 3]> {
+    let mut values = [0.0; 3];
+    let mut count = 0;
+    for word in words {
+        let value: f32 = word.parse()
+            .map_err(|_| anyhow!("invalid number '{}'", word))?;
+        if count < 3 {
+            values[count] = value;
+        }
+        count += 1;
+    }
+    if count < min {
+        bail!("expected at least {} numbers, found {}", min, count);
+    }
+    Ok(values)
+}
+
+// Zero based index from a 1 based (or negative, counting back from the
+// end) OBJ reference, or None when the reference is left out.
+fn index(word: Option<&str>, len: usize, what: &str) -> Result<Option<usize>> {
+    let word = match word {
+        None | Some("") => return Ok(None),
+        Some(word) => word,
+    };
+    let i: i64 = word.parse()
+        .map_err(|_| anyhow!("invalid {} index '{}'", what, word))?;
+    let resolved = match i {
+        0 => None,
+        i if i > 0 => Some(i as usize - 1),
+        i => len.checked_sub(i.unsigned_abs() as usize),
+    };
+    match resolved {
+        Some(r) if r < len => Ok(Some(r)),
+        _ => bail!("{} index {} out of range, {} defined so far", what, i, len),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+";
+
+    fn error(text: &str) -> String {
+        format!("{:#}", parse(text).unwrap_err())
+    }
+
+    #[test]
+    fn polygons_are_fanned() {
+        let text = format!("{}v 0.5 1.5 0\nf 1 2 3 5 4\n", SQUARE);
+        let (vertexes, indexes) = parse(&text).unwrap();
+        assert_eq!(vertexes.len(), 5);
+        assert_eq!(indexes, [0, 1, 2, 0, 2, 3, 0, 3, 4]);
+        // No vn, so normals are computed, facing +z for this winding.
+        assert!(vertexes.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn negative_indexes_count_back() {
+        let text = format!("{}f -4 -3 -2\nv 2 2 2\nf -1 -4 -3\n", SQUARE);
+        let (vertexes, indexes) = parse(&text).unwrap();
+        let positions: Vec<[f32; 3]> = indexes.iter()
+            .map(|&i| vertexes[i as usize].position)
+            .collect();
+        assert_eq!(positions, [
+            [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+            [2.0, 2.0, 2.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+        ]);
+    }
+
+    #[test]
+    fn vertexes_are_shared_by_reference() {
+        let text = format!("{}\
+vt 0 0
+vt 1 0.25
+vn 0 0 1
+f 1/1/1 2/2/1 3/2/1
+f 1/1/1 3/2/1 4//1
+f 1/2/1 3/2/1 4//1
+", SQUARE);
+        let (vertexes, indexes) = parse(&text).unwrap();
+        // 1/1/1, 2/2/1, 3/2/1, 4//1 and 1/2/1 are distinct
+        assert_eq!(vertexes.len(), 5);
+        assert_eq!(indexes, [0, 1, 2, 0, 2, 3, 4, 2, 3]);
+        assert_eq!(vertexes[1].tex_coord, [1.0, 0.75]);
+        assert_eq!(vertexes[3].tex_coord, [0.0, 0.0]);
+        assert_eq!(vertexes[4].position, vertexes[0].position);
+    }
+
+    #[test]
+    fn given_normals_are_kept() {
+        let text = format!("{}vn 0 1 0\nf 1//1 2//1 3//1\nf 1 3 4\n", SQUARE);
+        let (vertexes, indexes) = parse(&text).unwrap();
+        let normals: Vec<[f32; 3]> = indexes.iter()
+            .map(|&i| vertexes[i as usize].normal)
+            .collect();
+        assert_eq!(normals[..3], [[0.0, 1.0, 0.0]; 3]);
+        assert_eq!(normals[3..], [[0.0, 0.0, 1.0]; 3]);
+    }
+
+    #[test]
+    fn comments_and_other_statements_are_skipped() {
+        let text = format!("# square\no thing\n{}usemtl red\ns 1\nf 1 2 3 # one\n", SQUARE);
+        let (_, indexes) = parse(&text).unwrap();
+        assert_eq!(indexes, [0, 1, 2]);
+    }
+
+    #[test]
+    fn errors_give_the_line() {
+        assert_eq!(error("v 0 0\n"), "line 1: expected at least 3 numbers, found 2");
+        assert_eq!(error("v 0 0 x\n"), "line 1: invalid number 'x'");
+        let face = |f: &str| error(&format!("{}{}\n", SQUARE, f));
+        assert_eq!(face("f 1 2"), "line 5: face needs at least 3 vertexes, found 2");
+        assert_eq!(face("f 1 2 5"),
+            "line 5: position index 5 out of range, 4 defined so far");
+        assert_eq!(face("f 1 2 -5"),
+            "line 5: position index -5 out of range, 4 defined so far");
+        assert_eq!(face("f 1 2 3/1"),
+            "line 5: texture coordinate index 1 out of range, 0 defined so far");
+        assert_eq!(face("f 1 2 a"), "line 5: invalid position index 'a'");
+        assert_eq!(face("f 1 2 0"),
+            "line 5: position index 0 out of range, 4 defined so far");
+        assert_eq!(face("f 1 2 //1"), "line 5: face vertex '//1' has no position");
+        assert_eq!(error(SQUARE), "no faces found");
+    }
+}
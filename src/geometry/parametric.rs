@@ -0,0 +1,148 @@
+use std::f32::consts;
+use cgmath::{InnerSpace, Vector3};
+use crate::vertex::Vertex;
+use super::Options;
+
+// Rectangle in (u, v) sampled with usteps x vsteps quads. A wrapped
+// direction is periodic, f(max) == f(min), so its last row of vertexes
+// reuses the positions of the first to close the seam exactly. The seam
+// vertexes are still duplicated so texture coordinates don't jump back.
+pub struct Domain {
+    pub usteps: u32,
+    pub vsteps: u32,
+    pub umin: f32,
+    pub umax: f32,
+    pub vmin: f32,
+    pub vmax: f32,
+    pub wrap_u: bool,
+    pub wrap_v: bool,
+}
+
+pub fn make_parametric(domain: &Domain, f: impl Fn(f32, f32) -> [f32; 3])
+        -> (Vec<Vertex>, Vec<u32>) {
+
+    let ustep = (domain.umax - domain.umin) / domain.usteps as f32;
+    let vstep = (domain.vmax - domain.vmin) / domain.vsteps as f32;
+    let ucount = domain.usteps + 1;
+
+    // Normals are the cross product of the partial derivatives of f,
+    // taken by central differences.
+    let (hu, hv) = (1e-3 * ustep, 1e-3 * vstep);
+    let normal = |u: f32, v: f32| {
+        let fu = Vector3::from(f(u + hu, v)) - Vector3::from(f(u - hu, v));
+        let fv = Vector3::from(f(u, v + hv)) - Vector3::from(f(u, v - hv));
+        let n = fu.cross(fv);
+        if n.magnitude2() > 0.0 { n.normalize().into() } else { [0.0; 3] }
+    };
+
+    let mut vertexes: Vec<Vertex> = Vec::new();
+    for iv in 0..=domain.vsteps {
+        let sv = if domain.wrap_v && iv == domain.vsteps { 0 } else { iv };
+        let v = domain.vmin + sv as f32 * vstep;
+        for iu in 0..=domain.usteps {
+            let su = if domain.wrap_u && iu == domain.usteps { 0 } else { iu };
+            let u = domain.umin + su as f32 * ustep;
+            vertexes.push(Vertex {
+                position: f(u, v),
+                tex_coord: [iu as f32, 1.0 - iv as f32],
+                normal: normal(u, v),
+                scalar: 0.0,
+            });
+        }
+    }
+
+    let mut indexes: Vec<u32> = Vec::new();
+
+    for iv in 0..domain.vsteps {
+        let y = iv * ucount;
+        let y1 = (iv + 1) * ucount;
+        for iu in 0..domain.usteps {
+            indexes.push(iu + y);
+            indexes.push((iu + 1) + y1);
+            indexes.push(iu + y1);
+            indexes.push(iu + y);
+            indexes.push((iu + 1) + y);
+            indexes.push((iu + 1) + y1);
+        }
+    }
+
+    (vertexes, indexes)
+}
+
+pub fn mobius(opts: &Options) -> (Vec<Vertex>, Vec<u32>) {
+    let (usteps, vsteps) = opts.steps(32, 8);
+    // Not wrapped in u: the strip rejoins itself with v reversed.
+    let domain = Domain {
+        usteps, vsteps,
+        umin: 0.0, umax: consts::TAU,
+        vmin: -0.5, vmax: 0.5,
+        wrap_u: false, wrap_v: false,
+    };
+    make_parametric(&domain, |u, v| {
+        let r = 0.8 + v * f32::cos(u / 2.0);
+        [r * f32::cos(u), r * f32::sin(u), v * f32::sin(u / 2.0)]
+    })
+}
+
+pub fn klein(opts: &Options) -> (Vec<Vertex>, Vec<u32>) {
+    let (usteps, vsteps) = opts.steps(48, 16);
+    // Figure-8 immersion. Like the Möbius strip it rejoins in u with v
+    // reversed, so only v is wrapped.
+    let r = 2.0;
+    let scale = 0.3;
+    let domain = Domain {
+        usteps, vsteps,
+        umin: 0.0, umax: consts::TAU,
+        vmin: 0.0, vmax: consts::TAU,
+        wrap_u: false, wrap_v: true,
+    };
+    make_parametric(&domain, |u, v| {
+        let (sin_h, cos_h) = f32::sin_cos(u / 2.0);
+        let (sin_v, sin_2v) = (f32::sin(v), f32::sin(2.0 * v));
+        let w = r + cos_h * sin_v - sin_h * sin_2v;
+        [
+            scale * w * f32::cos(u),
+            scale * w * f32::sin(u),
+            scale * (sin_h * sin_v + cos_h * sin_2v),
+        ]
+    })
+}
+
+pub fn helicoid(opts: &Options) -> (Vec<Vertex>, Vec<u32>) {
+    let (usteps, vsteps) = opts.steps(32, 8);
+    let domain = Domain {
+        usteps, vsteps,
+        umin: -consts::TAU, umax: consts::TAU,
+        vmin: -1.0, vmax: 1.0,
+        wrap_u: false, wrap_v: false,
+    };
+    make_parametric(&domain, |u, v| {
+        [v * f32::cos(u), v * f32::sin(u), u / consts::TAU]
+    })
+}
+
+pub fn trefoil(opts: &Options) -> (Vec<Vertex>, Vec<u32>) {
+    let (usteps, vsteps) = opts.steps(96, 12);
+    // Tube of radius r swept along a trefoil knot using its Frenet frame.
+    let r = opts.tube_radius.unwrap_or(0.1);
+    let scale = 1.0 / 3.0;
+    let domain = Domain {
+        usteps, vsteps,
+        umin: 0.0, umax: consts::TAU,
+        vmin: 0.0, vmax: consts::TAU,
+        wrap_u: true, wrap_v: true,
+    };
+    make_parametric(&domain, |u, v| {
+        let (s1, c1) = f32::sin_cos(u);
+        let (s2, c2) = f32::sin_cos(2.0 * u);
+        let (s3, c3) = f32::sin_cos(3.0 * u);
+        let curve = Vector3::new(s1 + 2.0 * s2, c1 - 2.0 * c2, -s3) * scale;
+        let d1 = Vector3::new(c1 + 4.0 * c2, -s1 + 4.0 * s2, -3.0 * c3);
+        let d2 = Vector3::new(-s1 - 8.0 * s2, -c1 + 8.0 * c2, 9.0 * s3);
+        let tangent = d1.normalize();
+        let binormal = d1.cross(d2).normalize();
+        let normal = binormal.cross(tangent);
+        let p = curve + (normal * f32::cos(v) + binormal * f32::sin(v)) * r;
+        p.into()
+    })
+}
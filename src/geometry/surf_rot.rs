@@ -1,48 +1,73 @@
 use std::f32::consts;
 use crate::vertex::Vertex;
+use super::Options;
 
 pub fn surf_rot(steps: i32, pts: Vec<(f32, f32)>,
         ustep: f32, vstep: f32)
-        -> (Vec<Vertex>, Vec<u16>) {
+        -> (Vec<Vertex>, Vec<u32>) {
+    let normals = profile_normals(&pts);
+    surf_rot_normals(steps, pts, normals, ustep, vstep)
+}
+
+// Surface of revolution with a known normal (r, y) for each profile point.
+pub fn surf_rot_normals(steps: i32, pts: Vec<(f32, f32)>,
+        normals: Vec<(f32, f32)>, ustep: f32, vstep: f32)
+        -> (Vec<Vertex>, Vec<u32>) {
 
     let theta = consts::TAU / steps as f32;
     // println!("xstep = {}, ystep = {}", xstep, ystep);
     let mut vertexes: Vec<Vertex> = Vec::new();
-    for j in 0..pts.len() {
+    for (j, (&(r, y), &(nr, ny))) in pts.iter().zip(&normals).enumerate() {
         let fj = j as f32;
-        let (r, y) = pts[j];
         for i in 0..=steps {
             let fi = i as f32;
             let angle = theta * fi;
-            let (x, z) = (r * f32::cos(angle), r * f32::sin(angle));
+            let (cos, sin) = (f32::cos(angle), f32::sin(angle));
             let (u, v) = (0.0 + fi * ustep, 1.0 - fj * vstep);
             vertexes.push(Vertex {
-                position: [x, y, z],
-                tex_coord: [u, v]
+                position: [r * cos, y, r * sin],
+                tex_coord: [u, v],
+                normal: [nr * cos, ny, nr * sin],
+                scalar: 0.0,
             });
         }
     }
 
-    let mut indexes: Vec<u16> = Vec::new();
+    let mut indexes: Vec<u32> = Vec::new();
 
     for iy in 0..pts.len() as i32 - 1 {
         let y = iy * (steps + 1);
         let y1 = (iy + 1) * (steps + 1);
         for ix in 0..steps {
-            // quad_index(&mut indexes, ix as u16, y as u16, (ix+1) as u16, y1 as u16);
-            indexes.push((ix + y) as u16);
-            indexes.push((ix + y1) as u16);
-            indexes.push(((ix + 1) + y1) as u16);
-            indexes.push(((ix + 1) + y) as u16);
-            indexes.push((ix + y) as u16);
-            indexes.push(((ix + 1) + y1) as u16);
+            // quad_index(&mut indexes, ix as u32, y as u32, (ix+1) as u32, y1 as u32);
+            indexes.push((ix + y) as u32);
+            indexes.push((ix + y1) as u32);
+            indexes.push(((ix + 1) + y1) as u32);
+            indexes.push(((ix + 1) + y) as u32);
+            indexes.push((ix + y) as u32);
+            indexes.push(((ix + 1) + y1) as u32);
         }
     }
 
     (vertexes.to_vec(), indexes.to_vec())
 }
 
-pub fn quad_index(indexes: &mut Vec<u16>, x: u16, y: u16, x1: u16, y1: u16) {
+// Unit normals of a profile from the direction to its neighbouring points.
+pub fn profile_normals(pts: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    let last = pts.len().saturating_sub(1);
+    (0..pts.len()).map(|j| {
+        let (r0, y0) = pts[j.saturating_sub(1)];
+        let (r1, y1) = pts[usize::min(j + 1, last)];
+        unit(y1 - y0, r0 - r1)
+    }).collect()
+}
+
+fn unit(r: f32, y: f32) -> (f32, f32) {
+    let len = f32::hypot(r, y);
+    if len > 0.0 { (r / len, y / len) } else { (0.0, 0.0) }
+}
+
+pub fn quad_index(indexes: &mut Vec<u32>, x: u32, y: u32, x1: u32, y1: u32) {
     indexes.push(x + y);
     indexes.push(x1 + y);
     indexes.push(x + y1);
@@ -94,11 +119,11 @@ pub fn line(steps: u32, xmin: f32, xmax: f32, ymin: f32, ymax: f32)
     pts
 }
 
-pub fn cylinder()  -> (Vec<Vertex>, Vec<u16>) {
+pub fn cylinder()  -> (Vec<Vertex>, Vec<u32>) {
     surf_rot(8, line(8, 1.0, 1.0, -1.0, 1.0), 1.0, 1.0)
 }
 
-pub fn cone()  -> (Vec<Vertex>, Vec<u16>) {
+pub fn cone()  -> (Vec<Vertex>, Vec<u32>) {
     surf_rot(8, line(8, -1.0, 1.0, -1.0, 1.0), 1.0, 1.0)
 }
 
@@ -113,17 +138,26 @@ pub fn arc(steps: u32, tmin: f32, tmax: f32, r: f32)
     pts
 }
 
-pub fn torus()  -> (Vec<Vertex>, Vec<u16>) {
-    let mut circle: Vec<(f32, f32)> = arc(8, 0.0, consts::TAU, 0.1);
-    for i in 0..circle.len() { circle[i].0 += 0.5 }
+pub fn torus(opts: &Options)  -> (Vec<Vertex>, Vec<u32>) {
+    let (usteps, vsteps) = opts.steps(8, 8);
+    let radius = opts.radius.unwrap_or(0.5);
+    let tube_radius = opts.tube_radius.unwrap_or(0.1);
+    let mut circle: Vec<(f32, f32)> = arc(vsteps, 0.0, consts::TAU, tube_radius);
+    // Normals point away from the centre of the tube.
+    let normals = arc(vsteps, 0.0, consts::TAU, 1.0);
+    for pt in circle.iter_mut() { pt.0 += radius }
     // println!("circle len = {}", circle.len());
     // println!("{:#?}", circle);
-    surf_rot(8, circle, 1.0, 1.0)
+    surf_rot_normals(usteps as i32, circle, normals, 1.0, 1.0)
 }
 
-pub fn sphere()  -> (Vec<Vertex>, Vec<u16>) {
+pub fn sphere()  -> (Vec<Vertex>, Vec<u32>) {
     let pi_2 = consts::FRAC_PI_2;
-    surf_rot(8, arc(8, -pi_2, pi_2, 1.0), 1.0, 1.0)
+    let circle = arc(8, -pi_2, pi_2, 1.0);
+    // On a unit sphere the normal is the position, exact at the poles
+    // where differences along the profile tilt it.
+    let normals = circle.clone();
+    surf_rot_normals(8, circle, normals, 1.0, 1.0)
 }
 
 pub fn parabola(steps: u32, xmin: f32, xmax: f32) -> Vec<(f32, f32)> {
@@ -131,21 +165,19 @@ pub fn parabola(steps: u32, xmin: f32, xmax: f32) -> Vec<(f32, f32)> {
     let xspace = (xmax - xmin) / steps as f32;
     for i in 0..=steps {
         let x = xmin + xspace * i as f32;
-        let y = x * x as f32;
+        let y = x * x;
         pts.push((x, y));
     }
     pts
 }
 
-pub fn paraboloid()  -> (Vec<Vertex>, Vec<u16>) {
+pub fn paraboloid()  -> (Vec<Vertex>, Vec<u32>) {
     surf_rot(8, parabola(8, 0.0, 1.0), 1.0, 1.0)
 }
 
-pub fn hyperbola(steps: u32, a: f32, b: f32, c: f32)
+pub fn hyperbola(steps: u32, a: f32, b: f32, c: f32, ymin: f32, ymax: f32)
         -> Vec<(f32, f32)> {
     let mut pts: Vec<(f32, f32)> = Vec::with_capacity((steps + 1) as usize);
-    let ymin = -1.0;
-    let ymax = 1.0;
     let yspace = (ymax - ymin) / steps as f32;
     for i in 0..=steps {
         let y = ymin + yspace * i as f32;
@@ -157,8 +189,44 @@ pub fn hyperbola(steps: u32, a: f32, b: f32, c: f32)
     pts
 }
 
-pub fn hyperboloid(a: f32, b: f32, c: f32)  -> (Vec<Vertex>, Vec<u16>) {
-    surf_rot(8, hyperbola(8, a, b, c), 1.0, 1.0)
+// Profile runs over --ymin..--ymax along the axis of rotation.
+pub fn hyperboloid(a: f32, b: f32, c: f32, opts: &Options)
+        -> (Vec<Vertex>, Vec<u32>) {
+    let (usteps, vsteps) = opts.steps(8, 8);
+    let pts = hyperbola(vsteps, a, b, c, opts.ymin, opts.ymax);
+    // The gradient of r^2 + a y^2 + 2 b y + c is (r, a y + b), except at
+    // a cone's apex where it vanishes and the profile has to do.
+    let normals = pts.iter().zip(profile_normals(&pts))
+        .map(|(&(r, y), profile)| match unit(r, a * y + b) {
+            (0.0, 0.0) => profile,
+            normal => normal,
+        })
+        .collect();
+    surf_rot_normals(usteps as i32, pts, normals, 1.0, 1.0)
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_normals_are_positions(vertexes: &[Vertex]) {
+        for v in vertexes {
+            for k in 0..3 {
+                assert!((v.normal[k] - v.position[k]).abs() < 1e-5,
+                    "normal {:?} at {:?}", v.normal, v.position);
+            }
+        }
+    }
+
+    #[test]
+    fn sphere_normals_are_radial() {
+        let (vertexes, _) = sphere();
+        assert_normals_are_positions(&vertexes);
+        // The quadric preset, poles included
+        let (vertexes, _) = hyperboloid(1.0, 0.0, -1.0, &Options::default());
+        assert!(vertexes.iter().any(|v| v.position[1] == 1.0));
+        assert_normals_are_positions(&vertexes);
+    }
+}
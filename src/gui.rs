@@ -0,0 +1,222 @@
+// Side panel of live parameters, drawn with egui over the mesh in the
+// same render pass. G shows and hides it.
+use cgmath::Deg;
+use clap::ValueEnum;
+use winit::{event::WindowEvent, event_loop::EventLoopWindowTarget, window::Window};
+use crate::{cli, colormap, texture, State};
+
+pub struct Gui {
+    context: egui::Context,
+    state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    pub visible: bool,
+}
+
+// One frame of the panel, tessellated and ready to draw.
+pub struct Frame {
+    primitives: Vec<egui::ClippedPrimitive>,
+    textures_delta: egui::TexturesDelta,
+    screen: egui_wgpu::renderer::ScreenDescriptor,
+}
+
+impl Gui {
+    pub fn new<T>(
+        event_loop: &EventLoopWindowTarget<T>,
+        window: &Window,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let mut state = egui_winit::State::new(event_loop);
+        state.set_pixels_per_point(window.scale_factor() as f32);
+        state.set_max_texture_side(device.limits().max_texture_dimension_2d as usize);
+        // Drawn after the mesh in its pass, so it shares the depth and
+        // multisample targets.
+        let renderer = egui_wgpu::Renderer::new(device, format,
+            Some(texture::Depth::DEPTH_FORMAT), sample_count);
+        Self { context: egui::Context::default(), state, renderer, visible: true }
+    }
+
+    // Whether the panel took the event, so it shouldn't move the camera.
+    // While hidden nothing takes egui's input, so events aren't queued for
+    // it, except the scale factor which is only kept for the next frame.
+    pub fn on_event(&mut self, event: &WindowEvent) -> bool {
+        if !self.visible && !matches!(event, WindowEvent::ScaleFactorChanged { .. }) {
+            return false;
+        }
+        self.state.on_event(&self.context, event).consumed && self.visible
+    }
+
+    pub fn run(
+        &mut self,
+        window: &Window,
+        size: winit::dpi::PhysicalSize<u32>,
+        ui: impl FnOnce(&egui::Context),
+    ) -> Frame {
+        let input = self.state.take_egui_input(window);
+        let output = self.context.run(input, ui);
+        self.state.handle_platform_output(window, &self.context, output.platform_output);
+        Frame {
+            primitives: self.context.tessellate(output.shapes),
+            textures_delta: output.textures_delta,
+            screen: egui_wgpu::renderer::ScreenDescriptor {
+                size_in_pixels: [size.width, size.height],
+                pixels_per_point: self.context.pixels_per_point(),
+            },
+        }
+    }
+
+    // Uploads the frame's textures and vertexes, before its render pass.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &Frame,
+    ) -> Vec<wgpu::CommandBuffer> {
+        for (id, delta) in &frame.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        self.renderer.update_buffers(device, queue, encoder, &frame.primitives, &frame.screen)
+    }
+
+    pub fn paint<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>, frame: &'rp Frame) {
+        self.renderer.render(render_pass, &frame.primitives, &frame.screen);
+    }
+
+    // Frees textures the frame no longer needs, once it's been submitted.
+    pub fn finish(&mut self, frame: Frame) {
+        for id in &frame.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
+
+fn name<T: ValueEnum>(value: &T) -> String {
+    value.to_possible_value().unwrap().get_name().to_string()
+}
+
+impl State {
+    pub(crate) fn panel(&mut self, ctx: &egui::Context) {
+        egui::SidePanel::left("parameters").show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.heading("Geometry");
+                self.geometry_panel(ui);
+                ui.separator();
+                ui.heading("Colour");
+                self.color_panel(ui);
+                ui.separator();
+                ui.heading("Camera");
+                self.camera_panel(ui);
+            });
+        });
+    }
+
+    fn geometry_panel(&mut self, ui: &mut egui::Ui) {
+        let mut source = self.args.source;
+        egui::ComboBox::from_label("Source")
+            .selected_text(self.args.source_name())
+            .show_ui(ui, |ui| {
+                for s in self.args.sources() {
+                    ui.selectable_value(&mut source, s, self.args.name_of(s));
+                }
+            });
+        if source != self.args.source {
+            self.show(source);
+        }
+
+        let mut mode = self.args.display_mode;
+        egui::ComboBox::from_label("Display")
+            .selected_text(name(&mode))
+            .show_ui(ui, |ui| {
+                for &m in cli::DisplayMode::value_variants() {
+                    ui.selectable_value(&mut mode, m, name(&m));
+                }
+            });
+        if mode != self.args.display_mode {
+            self.set_display_mode(mode);
+        }
+
+        let mut reload = false;
+        // Coefficients of the quadric presets, kept for each one
+        if let cli::Source::Preset(geometry) = self.args.source {
+            if let Some((mut a, mut b, mut c)) = self.args.quadric(geometry) {
+                reload |= ui.add(egui::Slider::new(&mut a, -2.0..=2.0).text("a")).changed();
+                reload |= ui.add(egui::Slider::new(&mut b, -2.0..=2.0).text("b")).changed();
+                reload |= ui.add(egui::Slider::new(&mut c, -2.0..=2.0).text("c")).changed();
+                if reload {
+                    self.args.quadrics.insert(geometry, (a, b, c));
+                }
+            }
+        }
+
+        let opts = &mut self.args.options;
+        // Left unticked, each preset uses its own steps.
+        let mut steps = opts.u_steps.is_some() || opts.v_steps.is_some();
+        if ui.checkbox(&mut steps, "Grid steps").changed() {
+            let default = steps.then(|| opts.resolution.unwrap_or(32));
+            (opts.u_steps, opts.v_steps) = (default, default);
+            reload = true;
+        }
+        if steps {
+            let (mut u, mut v) = opts.steps(32, 32);
+            if ui.add(egui::Slider::new(&mut u, 2..=256).logarithmic(true).text("u")).changed()
+                | ui.add(egui::Slider::new(&mut v, 2..=256).logarithmic(true).text("v")).changed() {
+                (opts.u_steps, opts.v_steps) = (Some(u), Some(v));
+                reload = true;
+            }
+        }
+
+        if reload {
+            self.load_mesh();
+        }
+    }
+
+    fn color_panel(&mut self, ui: &mut egui::Ui) {
+        let mut builtin = self.args.colormap_builtin;
+        egui::ComboBox::from_label("Colour map")
+            .selected_text(builtin.map_or("file".to_string(), |b| name(&b)))
+            .show_ui(ui, |ui| {
+                for &b in colormap::Builtin::value_variants() {
+                    ui.selectable_value(&mut builtin, Some(b), name(&b));
+                }
+            });
+        if let Some(b) = builtin.filter(|&b| Some(b) != self.args.colormap_builtin) {
+            self.set_colormap(b);
+        }
+
+        let (mut low, mut high) = self.mesh.color_range;
+        let speed = f32::max(high - low, 1e-3) * 0.005;
+        // Kept apart like --color-range, as the shader divides by high - low.
+        let changed = ui.horizontal(|ui| {
+            ui.label("Range");
+            ui.add(egui::DragValue::new(&mut low).speed(speed)
+                .clamp_range(f32::MIN..=high - speed)).changed()
+                | ui.add(egui::DragValue::new(&mut high).speed(speed)
+                    .clamp_range(low + speed..=f32::MAX)).changed()
+        }).inner;
+        if changed && low < high {
+            self.set_color_range(Some((low, high)));
+        }
+        if ui.add_enabled(self.args.color_range.is_some(), egui::Button::new("Fit range")).clicked() {
+            self.set_color_range(None);
+        }
+    }
+
+    fn camera_panel(&mut self, ui: &mut egui::Ui) {
+        let controller = &mut self.camera_controller;
+        ui.add(egui::Slider::new(&mut controller.speed, 0.1..=20.0)
+            .logarithmic(true).text("speed"));
+        ui.add(egui::Slider::new(&mut controller.sensitivity, 0.05..=2.0)
+            .logarithmic(true).text("sensitivity"));
+
+        let p = self.camera.position;
+        let t = self.orbit.target;
+        ui.label(format!("position  {:.2} {:.2} {:.2}", p.x, p.y, p.z));
+        ui.label(format!("yaw {:.1}°  pitch {:.1}°",
+            Deg::from(self.camera.yaw).0, Deg::from(self.camera.pitch).0));
+        ui.label(format!("target  {:.2} {:.2} {:.2}", t.x, t.y, t.z));
+        ui.label(format!("distance  {:.2}", self.orbit.distance));
+        ui.label(format!("projection  {}", name(&self.projection.kind)));
+    }
+}
@@ -0,0 +1,150 @@
+// Rendering without a window, into a texture that is copied back to the
+// CPU and saved, for figures and regression images on machines with no
+// display or only a software adapter.
+use std::f32::consts;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+use anyhow::*;
+use cgmath::Rad;
+use image::RgbaImage;
+use crate::{cli, State};
+
+// Renders one frame at --size and writes it to path, or with --frames
+// a numbered image for each frame.
+pub async fn render(args: cli::Args, path: &Path) -> Result<()> {
+    env_logger::init();
+    let mut state = State::new(None, args).await;
+    let target = Target::new(&state);
+    let Some(frames) = state.args.frames.take() else {
+        state.update(Duration::ZERO);
+        return save(&target.capture(&mut state)?, path);
+    };
+
+    let digits = usize::max(4, (frames.count - 1).to_string().len());
+    // A sweep keeps one colour range, --color-range or else the first
+    // frame's, so colours compare across frames.
+    let mut color_range = state.args.color_range;
+    for i in 0..frames.count {
+        match frames.sweep {
+            // From one end of the sweep to the other
+            Some(sweep) => {
+                let t = i as f32 / u32::max(frames.count - 1, 1) as f32;
+                let t = frames.easing.apply(t);
+                state.args.set_param(sweep.param, sweep.from + (sweep.to - sweep.from) * t);
+                state.load_mesh();
+                let range = *color_range.get_or_insert(state.mesh.color_range);
+                state.set_color_range(Some(range));
+            }
+            // Once around, leaving out the last frame, which is the first
+            None => {
+                let t = frames.easing.apply(i as f32 / frames.count as f32);
+                state.model_view.turn(Rad(t * consts::TAU));
+            }
+        }
+        state.update(Duration::ZERO);
+        save(&target.capture(&mut state)?, &frame_path(path, i, digits))?;
+    }
+    Ok(())
+}
+
+fn save(img: &RgbaImage, path: &Path) -> Result<()> {
+    img.save(path)
+        .with_context(|| format!("saving {}", path.display()))
+}
+
+// path with the frame number before its extension, turn.png becoming
+// turn_0000.png, turn_0001.png and so on, for ffmpeg -i turn_%04d.png.
+fn frame_path(path: &Path, frame: u32, digits: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}_{:0digits$}", stem, frame, digits = digits);
+    if let Some(ext) = path.extension() {
+        name = format!("{}.{}", name, ext.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+// Texture to draw into, and a buffer to copy it to for reading.
+pub(crate) struct Target {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    buffer: wgpu::Buffer,
+    // Rows in the buffer are padded to COPY_BYTES_PER_ROW_ALIGNMENT.
+    padded_bytes_per_row: u32,
+}
+
+impl Target {
+    pub(crate) fn new(state: &State) -> Self {
+        let (width, height) = (state.config.width, state.config.height);
+        let texture = state.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: state.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (width * 4).div_ceil(align) * align;
+        let buffer = state.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self { texture, view, buffer, padded_bytes_per_row }
+    }
+
+    // Draws a frame with the state's current camera and reads it back.
+    pub(crate) fn capture(&self, state: &mut State) -> Result<RgbaImage> {
+        let (width, height) = (state.config.width, state.config.height);
+        state.draw(&self.view, None);
+
+        let mut encoder = state.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(self.padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+            },
+            self.texture.size(),
+        );
+        state.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = self.buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        state.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()?.context("reading back the frame")?;
+
+        let row_bytes = (width * 4) as usize;
+        let mut pixels = Vec::with_capacity(row_bytes * height as usize);
+        for row in slice.get_mapped_range().chunks_exact(self.padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..row_bytes]);
+        }
+        self.buffer.unmap();
+        // Opaque like the window, whatever alpha the fragment shader wrote.
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+        RgbaImage::from_raw(width, height, pixels)
+            .ok_or_else(|| anyhow!("frame readback is the wrong size"))
+    }
+}
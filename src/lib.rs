@@ -1,7 +1,6 @@
 use std::iter;
 
 use wgpu::util::DeviceExt;
-use wgpu;
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
@@ -12,13 +11,22 @@ use winit::{
 use wasm_bindgen::prelude::*;
 
 use image::{RgbaImage, Rgba, DynamicImage};
+use clap::ValueEnum;
 
 pub mod vertex;
+pub mod curvature;
+pub mod wireframe;
 pub mod cli;
+pub mod expr;
+pub mod colormap;
+pub mod export;
 // mod texture_wire;
-mod texture;
-mod geometry;
-mod camera;
+pub mod texture;
+pub mod geometry;
+pub mod camera;
+pub mod headless;
+pub mod pipeline;
+pub mod gui;
 
 // #[rustfmt::skip]
 // pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -51,6 +59,9 @@ mod camera;
 struct CameraUniform {
     view_position: [f32; 4],
     view_proj: [[f32; 4]; 4],
+    // Model transform and its inverse transpose for lighting in world space.
+    model: [[f32; 4]; 4],
+    normal: [[f32; 4]; 4],
 }
 
 impl CameraUniform {
@@ -59,6 +70,8 @@ impl CameraUniform {
         Self {
             view_position: [0.0; 4],
             view_proj: cgmath::Matrix4::identity().into(),
+            model: cgmath::Matrix4::identity().into(),
+            normal: cgmath::Matrix4::identity().into(),
         }
     }
 
@@ -68,10 +81,43 @@ impl CameraUniform {
         projection: &camera::Projection,
         model_view: &camera::ModelView
     ) {
+        use cgmath::{Matrix, SquareMatrix};
         // self.view_proj = (OPENGL_TO_WGPU_MATRIX * camera.build_view_projection_matrix()).into();
+        let model = model_view.calc_matrix();
         self.view_position = camera.position.to_homogeneous().into();
         self.view_proj = (projection.calc_matrix() * camera.calc_matrix()
-            * model_view.calc_matrix()).into();
+            * model).into();
+        self.model = model.into();
+        self.normal = model.invert().unwrap_or(model).transpose().into();
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColorRangeUniform {
+    low: f32,
+    high: f32,
+    // Uniform buffers are padded to 16 bytes for WebGL.
+    _padding: [f32; 2],
+}
+
+impl ColorRangeUniform {
+    fn new((low, high): (f32, f32)) -> Self {
+        Self { low, high, _padding: [0.0; 2] }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct EdgeUniform {
+    color: [f32; 4],
+    width: f32,
+    _padding: [f32; 3],
+}
+
+impl EdgeUniform {
+    fn new(color: [f32; 4], width: f32) -> Self {
+        Self { color, width, _padding: [0.0; 3] }
     }
 }
 
@@ -175,38 +221,149 @@ impl CameraUniform {
 //     }
 // }
 
+// The mesh from args in GPU buffers, with its color range and bounds.
+struct Mesh {
+    vertex_buffer: wgpu::Buffer,
+    barycentric_buffer: Option<wgpu::Buffer>,
+    index_buffer: wgpu::Buffer,
+    index_format: wgpu::IndexFormat,
+    num_indices: u32,
+    color_range: (f32, f32),
+    bounds: camera::Bounds,
+    // What the vertexes and indexes were made for, see pipeline::Key.
+    line_list: bool,
+    edges: bool,
+}
+
+impl Mesh {
+    fn new(device: &wgpu::Device, args: &cli::Args, line_list: bool) -> Self {
+        let (mut vertexes, mut indexes) = args.make();
+        let color_range = args.color_range(&vertexes);
+        let bounds = camera::Bounds::new(&vertexes);
+
+        if line_list {
+            log::info!("No POLYGON_MODE_LINE, drawing the wire frame as lines");
+            indexes = wireframe::edge_indexes(&indexes);
+        }
+
+        let barycentric_buffer = args.drawn_edges().map(|edges| {
+            let barycentric = wireframe::barycentric(&vertexes, &indexes, edges == cli::Edges::Quads);
+            vertexes = wireframe::unshare(&vertexes, &indexes);
+            indexes = (0..indexes.len() as u32).collect();
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Barycentric Buffer"),
+                contents: bytemuck::cast_slice(&barycentric),
+                usage: wgpu::BufferUsages::VERTEX,
+            })
+        });
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertexes),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let (index_bytes, index_format) = vertex::index_data(&indexes);
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: &index_bytes,
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            vertex_buffer,
+            barycentric_buffer,
+            index_buffer,
+            index_format,
+            num_indices: indexes.len() as u32,
+            color_range,
+            bounds,
+            line_list,
+            edges: args.drawn_edges().is_some(),
+        }
+    }
+}
+
+// The value after value in values, wrapping around.
+fn next<T: Copy + PartialEq>(values: &[T], value: T) -> T {
+    let i = values.iter().position(|&v| v == value).map_or(0, |i| i + 1);
+    values[i % values.len()]
+}
+
+// Wire frames are drawn as a list of edges where the adapter can't draw
+// polygons as lines.
+fn line_list(args: &cli::Args, polygon_mode_line: bool) -> bool {
+    args.display_mode.polygon_mode() == wgpu::PolygonMode::Line && !polygon_mode_line
+}
+
 struct State {
-    surface: wgpu::Surface,
+    surface: Option<wgpu::Surface>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
+    pipelines: pipeline::Cache,
+    // What to draw, kept for rebuilding the mesh.
+    args: cli::Args,
+    mesh: Mesh,
+    // Whether the adapter can draw PolygonMode::Line.
+    polygon_mode_line: bool,
     #[allow(dead_code)]
     // diffuse_texture: texture_wire::Texture,
     diffuse_bind_group: wgpu::BindGroup,
     ctab_bind_group: wgpu::BindGroup,
+    style_bind_group: wgpu::BindGroup,
+    color_range_buffer: wgpu::Buffer,
     depth: texture::Depth,
+    sample_count: u32,
+    // Color target the render pass resolves from, with --msaa above 1.
+    multisample: Option<texture::Multisample>,
     // NEW!
     camera: camera::Camera,
     projection: camera::Projection,
     model_view: camera::ModelView,
+    orbit: camera::Orbit,
     camera_controller: camera::CameraController,
-    mouse_pressed: bool,
     camera_uniform: CameraUniform,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
+    // Parameter panel, with a window.
+    gui: Option<gui::Gui>,
+}
+
+// The requested sample count if both the surface and depth formats support
+// it, or else the largest that they do.
+fn sample_count(adapter: &wgpu::Adapter, features: wgpu::Features,
+        format: wgpu::TextureFormat, requested: u32) -> u32 {
+    let adapter_specific = features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+        || !adapter.get_downlevel_capabilities().is_webgpu_compliant();
+    let flags = |format: wgpu::TextureFormat| if adapter_specific {
+        adapter.get_texture_format_features(format).flags
+    } else {
+        format.describe().guaranteed_format_features.flags
+    };
+    let (color, depth) = (flags(format), flags(texture::Depth::DEPTH_FORMAT));
+    let supported = |count: u32| count == 1 || (color.sample_count_supported(count)
+        && depth.sample_count_supported(count)
+        && color.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE));
+    let count = [8, 4, 2, 1].into_iter()
+        .find(|&count| count <= requested && supported(count))
+        .unwrap_or(1);
+    if count != requested {
+        log::warn!("{:?} doesn't support {}x MSAA, using {}x", format, requested, count);
+    }
+    count
 }
 
 impl State {
+    // Without a window there's no surface, and frames are drawn into
+    // textures given to draw() at the --size from args.
     async fn new(
-        window: &Window,
+        window: Option<&Window>,
         args: cli::Args
     ) -> Self {
-        let size = window.inner_size();
+        let size = window.map_or_else(
+            || winit::dpi::PhysicalSize::new(args.size.0, args.size.1),
+            |window| window.inner_size());
 
         // The instance is a handle to our GPU
         // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
@@ -215,24 +372,41 @@ impl State {
             dx12_shader_compiler: Default::default()
             // dx12_shader_compiler: wgpu::Dx12Compiler::Fxc
         });
-        let surface = (unsafe { instance.create_surface(window) })
+        let surface = window.map(|window| (unsafe { instance.create_surface(window) })
             .unwrap_or_else(|error|{
-                panic!("Error creating surface, {}", error.to_string())
-            });
-        let adapter = instance
+                panic!("Error creating surface, {}", error)
+            }));
+        let mut adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
+                compatible_surface: surface.as_ref(),
                 force_fallback_adapter: false,
             })
-            .await
-            .unwrap();
+            .await;
+        // Build servers may only have a software adapter.
+        if adapter.is_none() && surface.is_none() {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    compatible_surface: None,
+                    force_fallback_adapter: true,
+                })
+                .await;
+        }
+        let adapter = adapter.expect("No suitable GPU adapter");
+        // WebGL and other downlevel adapters can't draw polygons as lines,
+        // so wire frames fall back to a list of edges.
+        // Sample counts other than 1 and 4 depend on the adapter's format
+        // features, which the device only allows when asked for.
+        let features = adapter.features() & (wgpu::Features::POLYGON_MODE_LINE
+            | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        let polygon_mode_line = features.contains(wgpu::Features::POLYGON_MODE_LINE);
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
                     // features: wgpu::Features::empty(),
-                    features: wgpu::Features::POLYGON_MODE_LINE,
+                    features,
                     // WebGL doesn't support all of wgpu's features, so if
                     // we're building for the web we'll have to disable some.
                     limits: if cfg!(target_arch = "wasm32") {
@@ -255,14 +429,20 @@ impl State {
         //     alpha_mode: wgpu::CompositeAlphaMode::Auto,
         // };
 
-        let surface_caps = surface.get_capabilities(&adapter);
+        // Offscreen targets are sRGB RGBA, the way PNGs are stored.
+        let surface_caps = surface.as_ref().map_or_else(
+            || wgpu::SurfaceCapabilities {
+                formats: vec![wgpu::TextureFormat::Rgba8UnormSrgb],
+                present_modes: vec![wgpu::PresentMode::Fifo],
+                alpha_modes: vec![wgpu::CompositeAlphaMode::Auto],
+            },
+            |surface| surface.get_capabilities(&adapter));
         // Shader code in this tutorial assumes an sRGB surface texture. Using a different
         // one will result all the colors coming out darker. If you want to support non
         // sRGB surfaces, you'll need to account for that when drawing to the frame.
         let surface_format = surface_caps.formats.iter()
             .copied()
-            .filter(|f| f.describe().srgb)
-            .next()
+            .find(|f| f.describe().srgb)
             .unwrap_or(surface_caps.formats[0]);
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -275,9 +455,15 @@ impl State {
         };
 
 
-        surface.configure(&device, &config);
+        if let Some(surface) = &surface {
+            surface.configure(&device, &config);
+        }
+
+        let sample_count = sample_count(&adapter, features, config.format, args.msaa);
+        let multisample = (sample_count > 1).then(||
+            texture::Multisample::create(&device, &config, sample_count, "multisample_texture"));
 
-        // Create wire texture
+        // Create wire texture, the diffuse texture unless --texture gives one
 
         let sizewire = 128;
         let sizewire1 = sizewire - 1;
@@ -295,24 +481,24 @@ impl State {
             wires.put_pixel(x, sizewire1, rgba);
         }
 
-        let diffuse_texture =
-            texture::Texture::from_image(&device, &queue,
-            &DynamicImage::ImageRgba8(wires), "Wire Frame").unwrap();
+        let mut sampling = args.texture_sampling;
+        if !adapter.get_downlevel_capabilities().flags
+                .contains(wgpu::DownlevelFlags::ANISOTROPIC_FILTERING) {
+            sampling.anisotropy = 1;
+        }
+        let diffuse_texture = match &args.texture {
+            Some(img) => texture::Texture::from_image_sampled(&device, &queue,
+                img, "Diffuse", &sampling).unwrap(),
+            None => texture::Texture::from_image_sampled(&device, &queue,
+                &DynamicImage::ImageRgba8(wires), "Wire Frame", &sampling).unwrap(),
+        };
 
         let diffuse_bind_group_layout = diffuse_texture.bind_group_layout;
         let diffuse_bind_group = diffuse_texture.bind_group;
 
         // Create color table texture
 
-        let size_ctab = 6;
-        let mut ctab = RgbaImage::new(size_ctab, 1);
-
-        ctab.put_pixel(0, 0, Rgba([255, 0, 255, 255]));
-        ctab.put_pixel(1, 0, Rgba([0, 0, 255, 255]));
-        ctab.put_pixel(2, 0, Rgba([0, 255, 255, 255]));
-        ctab.put_pixel(3, 0, Rgba([0, 255, 0, 255]));
-        ctab.put_pixel(4, 0, Rgba([255, 255, 0, 255]));
-        ctab.put_pixel(5, 0, Rgba([255, 0, 0, 255]));
+        let ctab = args.color_table();
 
         let ctab_texture =
             texture::Texture::from_image(&device, &queue,
@@ -321,7 +507,7 @@ impl State {
         let ctab_bind_group_layout = ctab_texture.bind_group_layout;
         let ctab_bind_group = ctab_texture.bind_group;
 
-        let depth = texture::Depth::create(&device, &config, "depth_texture");
+        let depth = texture::Depth::create(&device, &config, sample_count, "depth_texture");
     
         // let diffuse_bytes = include_bytes!("happy-tree.png");
         // let diffuse_texture =
@@ -370,9 +556,10 @@ impl State {
             cgmath::Deg(0.0), cgmath::Deg(0.0));
         let camera = camera::Camera::new(
             (0.0, 0.0, 3.0), cgmath::Deg(-90.0), cgmath::Deg(0.0));
-        let projection = camera::Projection::new(
+        let projection = camera::Projection::new(args.projection,
             config.width, config.height, cgmath::Deg(45.0), 0.1, 100.0);
-        let camera_controller = camera::CameraController::new(4.0, 0.4);
+        let orbit = camera::Orbit::new(&camera, (0.0, 0.0, 0.0));
+        let camera_controller = camera::CameraController::new(4.0, 0.4, args.camera_mode);
 
         // ...
 
@@ -403,7 +590,7 @@ impl State {
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -423,6 +610,34 @@ impl State {
             label: Some("camera_bind_group"),
         });
 
+        // Color range and wire frame style
+        let style_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("style_bind_group_layout"),
+            });
+
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Shader"),
             source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
@@ -434,116 +649,294 @@ impl State {
                 bind_group_layouts: &[
                     &diffuse_bind_group_layout,
                     &camera_bind_group_layout,
-                    &ctab_bind_group_layout],
+                    &ctab_bind_group_layout,
+                    &style_bind_group_layout],
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[vertex::Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                // entry_point: "fs_main",
-                entry_point: &args.frag_entry,
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: args.front_face,
-                cull_mode: args.cull_mode,
-                // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
-                // or Features::POLYGON_MODE_POINT
-                polygon_mode: args.polygon_mode,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            // depth_stencil: None,
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: texture::Depth::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less, // 1.
-                stencil: wgpu::StencilState::default(), // 2.
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            // If the pipeline will be used with a multiview render pass, this
-            // indicates how many array layers the attachments will have.
-            multiview: None,
-        });
+        let pipelines = pipeline::Cache::new(shader, render_pipeline_layout,
+            config.format, sample_count);
 
-        let (vertexes, indexes) =
-            args.geometry.make();
+        let line_list = line_list(&args, polygon_mode_line);
+        let mesh = Mesh::new(&device, &args, line_list);
 
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(&vertexes),
-            usage: wgpu::BufferUsages::VERTEX,
+        let color_range_uniform = ColorRangeUniform::new(mesh.color_range);
+        let color_range_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Color Range Buffer"),
+            contents: bytemuck::cast_slice(&[color_range_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(&indexes),
-            usage: wgpu::BufferUsages::INDEX,
+        let edge_uniform = EdgeUniform::new(args.edge_color, args.edge_width);
+        let edge_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Edge Buffer"),
+            contents: bytemuck::cast_slice(&[edge_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let style_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &style_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: color_range_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: edge_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("style_bind_group"),
         });
-        let num_indices = indexes.len() as u32;
 
-        Self {
+        let mut state = Self {
             surface,
             device,
             queue,
             config,
             size,
-            render_pipeline,
-            vertex_buffer,
-            index_buffer,
-            num_indices,
+            pipelines,
+            args,
+            mesh,
+            polygon_mode_line,
             // diffuse_texture,
             diffuse_bind_group,
             ctab_bind_group,
+            style_bind_group,
+            color_range_buffer,
             depth,
+            sample_count,
+            multisample,
             camera,
             projection,
             model_view,
+            orbit,
             camera_controller,
             camera_buffer,
             camera_bind_group,
             camera_uniform,
-            mouse_pressed: false,
+            gui: None,
+        };
+        state.frame_all();
+        state
+    }
+
+    // Remakes the mesh from args, keeping the view.
+    fn load_mesh(&mut self) {
+        let line_list = line_list(&self.args, self.polygon_mode_line);
+        self.mesh = Mesh::new(&self.device, &self.args, line_list);
+        self.queue.write_buffer(
+            &self.color_range_buffer,
+            0,
+            bytemuck::cast_slice(&[ColorRangeUniform::new(self.mesh.color_range)]),
+        );
+    }
+
+    fn set_color_range(&mut self, range: Option<(f32, f32)>) {
+        self.args.color_range = range;
+        match range {
+            Some(range) => {
+                self.mesh.color_range = range;
+                self.queue.write_buffer(
+                    &self.color_range_buffer,
+                    0,
+                    bytemuck::cast_slice(&[ColorRangeUniform::new(range)]),
+                );
+            }
+            // Back to the mesh's own range
+            None => self.load_mesh(),
         }
     }
 
+    fn set_colormap(&mut self, builtin: colormap::Builtin) {
+        self.args.colormap = colormap::ColorMap::builtin(builtin);
+        self.args.colormap_builtin = Some(builtin);
+        let ctab_texture = texture::Texture::from_image(&self.device, &self.queue,
+            &DynamicImage::ImageRgba8(self.args.color_table()), "Color Table").unwrap();
+        self.ctab_bind_group = ctab_texture.bind_group;
+        // Exported vertex colors come from the map too.
+        self.load_mesh();
+    }
+
+    // Draws another of args.sources(), framed.
+    fn show(&mut self, source: cli::Source) {
+        self.args.source = source;
+        self.load_mesh();
+        self.frame_all();
+    }
+
+    fn title(&self) -> String {
+        let cull = match self.args.cull_mode {
+            None => "none",
+            Some(wgpu::Face::Front) => "front",
+            Some(wgpu::Face::Back) => "back",
+        };
+        let front_face = match self.args.front_face {
+            wgpu::FrontFace::Ccw => "ccw",
+            wgpu::FrontFace::Cw => "cw",
+        };
+        format!("triangles_wgpu - {} ({}, cull {}, {})", self.args.source_name(),
+            self.args.display_mode.to_possible_value().unwrap().get_name(), cull, front_face)
+    }
+
+    fn pipeline_key(&self) -> pipeline::Key {
+        let mode = self.args.display_mode;
+        pipeline::Key {
+            frag_entry: mode.frag_entry(),
+            polygon_mode: mode.polygon_mode(),
+            cull_mode: self.args.cull_mode,
+            front_face: self.args.front_face,
+            blend: mode.blend(),
+            edges: self.mesh.edges,
+            line_list: self.mesh.line_list,
+        }
+    }
+
+    // M, C and O step through display modes, cull modes and front faces.
+    fn cycle_display(&mut self, key: VirtualKeyCode) {
+        match key {
+            VirtualKeyCode::M => self.set_display_mode(
+                next(cli::DisplayMode::value_variants(), self.args.display_mode)),
+            VirtualKeyCode::C => {
+                self.args.cull_mode = next(
+                    &[None, Some(wgpu::Face::Back), Some(wgpu::Face::Front)],
+                    self.args.cull_mode);
+            }
+            VirtualKeyCode::O => {
+                self.args.front_face = next(
+                    &[wgpu::FrontFace::Ccw, wgpu::FrontFace::Cw], self.args.front_face);
+            }
+            _ => {}
+        }
+    }
+
+    fn set_display_mode(&mut self, mode: cli::DisplayMode) {
+        self.args.display_mode = mode;
+        // Edges and line list wire frames need their own vertexes.
+        if self.mesh.edges != self.args.drawn_edges().is_some()
+                || self.mesh.line_list != line_list(&self.args, self.polygon_mode_line) {
+            self.load_mesh();
+        }
+    }
+
+    // Back to looking down -z at the whole mesh, unrotated.
+    fn frame_all(&mut self) {
+        self.camera = camera::Camera::new(
+            self.camera.position, cgmath::Deg(-90.0), cgmath::Deg(0.0));
+        self.model_view = camera::ModelView::new(cgmath::Deg(0.0), cgmath::Deg(0.0));
+        self.model_view.center = self.mesh.bounds.center;
+        self.orbit = camera::Orbit {
+            target: self.model_view.world_center(),
+            distance: self.projection.fit_distance(self.mesh.bounds.radius),
+        };
+    }
+
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.projection.resize(new_size.width, new_size.height);
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
-            self.depth = texture::Depth::create(&self.device, &self.config, "depth_texture");
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
+            self.depth = texture::Depth::create(&self.device, &self.config,
+                self.sample_count, "depth_texture");
+            if self.multisample.is_some() {
+                self.multisample = Some(texture::Multisample::create(&self.device,
+                    &self.config, self.sample_count, "multisample_texture"));
+            }
             // self.camera.aspect = self.config.width as f32 / self.config.height as f32;
         }
     }
 
     fn input(&mut self, event: &WindowEvent) -> bool {
+        let consumed = self.gui.as_mut().is_some_and(|gui| gui.on_event(event));
+        // Releases still reach the controller, to end drags begun outside
+        // the panel.
+        let release = matches!(event,
+            WindowEvent::MouseInput { state: ElementState::Released, .. });
+        if consumed && !release {
+            return true;
+        }
         match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::G),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if self.gui.is_some() => {
+                let gui = self.gui.as_mut().unwrap();
+                gui.visible = !gui.visible;
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::P),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.projection.toggle();
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.frame_all();
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key @ (VirtualKeyCode::PageUp | VirtualKeyCode::PageDown)),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.args.step_source(if *key == VirtualKeyCode::PageUp { -1 } else { 1 });
+                self.show(self.args.source);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key @ (VirtualKeyCode::M | VirtualKeyCode::C | VirtualKeyCode::O)),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                self.cycle_display(*key);
+                true
+            }
+            // 1 to 9 then 0 pick the first ten of args.sources()
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if (VirtualKeyCode::Key1 as usize..=VirtualKeyCode::Key0 as usize)
+                    .contains(&(*key as usize)) => {
+                let index = *key as usize - VirtualKeyCode::Key1 as usize;
+                if let Some(&source) = self.args.sources().get(index) {
+                    self.show(source);
+                }
+                true
+            }
             WindowEvent::KeyboardInput {
                 input:
                     KeyboardInput {
@@ -554,25 +947,24 @@ impl State {
                 ..
             } => self.camera_controller.process_keyboard(*key, *state),
             WindowEvent::MouseWheel { delta, .. } => {
-                // self.camera_controller.process_scroll(delta);
-                // self.camera_controller.process_mouse(delta, delta);
+                self.camera_controller.process_scroll(delta);
                 true
             }
             WindowEvent::MouseInput {
-                button: MouseButton::Left,
+                button,
                 state,
                 ..
-            } => {
-                self.mouse_pressed = *state == ElementState::Pressed;
-                true
-            }
+            } => self.camera_controller.process_button(*button, *state),
             _ => false,
         }
     }
 
     fn update(&mut self, dt: std::time::Duration) {
         self.camera_controller.update_model_view(&mut self.model_view, dt);
-        self.camera_controller.update_camera(&mut self.camera, dt);
+        self.camera_controller.update_camera(&mut self.camera, &mut self.orbit, dt);
+        self.projection.zoom(self.orbit.distance);
+        self.projection.fit_depth(&self.camera, self.model_view.world_center(),
+            self.mesh.bounds.radius);
         self.camera_uniform.update_view_proj(&self.camera, &self.projection,
             &self.model_view);
         // println!("{:?}", self.camera_uniform);
@@ -583,24 +975,52 @@ impl State {
         );
     }
 
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+    fn render(&mut self, window: &Window) -> Result<(), wgpu::SurfaceError> {
+        let surface = self.surface.as_ref().expect("render() needs a window");
+        let output = surface.get_current_texture()?;
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
+        match self.gui.take() {
+            Some(mut gui) if gui.visible => {
+                let frame = gui.run(window, self.size, |ctx| self.panel(ctx));
+                self.draw(&view, Some((&mut gui, &frame)));
+                gui.finish(frame);
+                self.gui = Some(gui);
+            }
+            gui => {
+                self.gui = gui;
+                self.draw(&view, None);
+            }
+        }
+        output.present();
+
+        Ok(())
+    }
 
+    // Draws a frame into view, a texture of the config's size and format,
+    // with the panel over it if there's one.
+    fn draw(&mut self, view: &wgpu::TextureView, overlay: Option<(&mut gui::Gui, &gui::Frame)>) {
+        let pipeline = self.pipelines.get(&self.device, self.pipeline_key());
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
+        let (overlay, uploads) = match overlay {
+            Some((gui, frame)) => {
+                let uploads = gui.prepare(&self.device, &self.queue, &mut encoder, frame);
+                (Some((&*gui, frame)), uploads)
+            }
+            None => (None, Vec::new()),
+        };
 
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
+                    view: self.multisample.as_ref().map_or(view, |ms| &ms.view),
+                    resolve_target: self.multisample.is_some().then_some(view),
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
                             r: 0.1,
@@ -623,19 +1043,23 @@ impl State {
             
             });
 
-            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_pipeline(pipeline);
             render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
             render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
             render_pass.set_bind_group(2, &self.ctab_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+            render_pass.set_bind_group(3, &self.style_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.mesh.vertex_buffer.slice(..));
+            if let Some(barycentric_buffer) = &self.mesh.barycentric_buffer {
+                render_pass.set_vertex_buffer(1, barycentric_buffer.slice(..));
+            }
+            render_pass.set_index_buffer(self.mesh.index_buffer.slice(..), self.mesh.index_format);
+            render_pass.draw_indexed(0..self.mesh.num_indices, 0, 0..1);
+            if let Some((gui, frame)) = overlay {
+                gui.paint(&mut render_pass, frame);
+            }
         }
 
-        self.queue.submit(iter::once(encoder.finish()));
-        output.present();
-
-        Ok(())
+        self.queue.submit(uploads.into_iter().chain(iter::once(encoder.finish())));
     }
 }
 
@@ -673,36 +1097,42 @@ pub async fn run(args: cli::Args) {
     }
 
     // State::new uses async code, so we're going to wait for it to finish
-    let mut state = State::new(&window, args).await;
+    let mut state = State::new(Some(&window), args).await;
+    state.gui = Some(gui::Gui::new(&event_loop, &window, &state.device,
+        state.config.format, state.sample_count));
+    let mut title = state.title();
+    window.set_title(&title);
     let mut last_render_time = instant::Instant::now();
 
     event_loop.run(move |event, _, control_flow| {
         match event {
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => state.camera_controller.process_mouse(delta.0, delta.1),
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == window.id() => {
-                if !state.input(event) {
-                    match event {
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            input:
-                                KeyboardInput {
-                                    state: ElementState::Pressed,
-                                    virtual_keycode: Some(VirtualKeyCode::Escape),
-                                    ..
-                                },
-                            ..
-                        } => *control_flow = ControlFlow::Exit,
-                        WindowEvent::Resized(physical_size) => {
-                            state.resize(*physical_size);
-                        }
-                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            // new_inner_size is &mut so w have to dereference it twice
-                            state.resize(**new_inner_size);
-                        }
-                        _ => {}
+            } if window_id == window.id() && !state.input(event) => {
+                match event {
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => *control_flow = ControlFlow::Exit,
+                    WindowEvent::Resized(physical_size) => {
+                        state.resize(*physical_size);
+                    }
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        // new_inner_size is &mut so w have to dereference it twice
+                        state.resize(**new_inner_size);
                     }
+                    _ => {}
                 }
             }
             Event::RedrawRequested(window_id) if window_id == window.id() => {
@@ -710,7 +1140,7 @@ pub async fn run(args: cli::Args) {
                 let dt = now - last_render_time;
                 last_render_time = now;
                 state.update(dt);
-                match state.render() {
+                match state.render(&window) {
                     Ok(_) => {}
                     // Reconfigure the surface if it's lost or outdated
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => state.resize(state.size),
@@ -721,6 +1151,10 @@ pub async fn run(args: cli::Args) {
                 }
             }
             Event::MainEventsCleared => {
+                if state.title() != title {
+                    title = state.title();
+                    window.set_title(&title);
+                }
                 // RedrawRequested will only trigger once, unless we manually
                 // request it.
                 window.request_redraw();
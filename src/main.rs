@@ -1,8 +1,20 @@
 use triangles_wgpu::run;
 use triangles_wgpu::cli;
+use triangles_wgpu::export;
+use triangles_wgpu::headless;
 
-fn main() {
+fn main() -> anyhow::Result<()> {
 
-    pollster::block_on(run(cli::Args::new()));
+    let args = cli::Args::new();
+    if let Some(target) = &args.export {
+        let (vertexes, indexes) = args.make();
+        return export::export(&target.path, target.format, &vertexes, &indexes,
+            &args.color_table(), args.color_range(&vertexes));
+    }
+    if let Some(path) = args.output.clone() {
+        return pollster::block_on(headless::render(args, &path));
+    }
+    pollster::block_on(run(args));
+    Ok(())
 
 }
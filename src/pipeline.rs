@@ -0,0 +1,110 @@
+// Render pipelines for each combination of display settings, compiled the
+// first time they're drawn with and kept for switching back to.
+use std::collections::HashMap;
+use crate::{texture, vertex, wireframe};
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Key {
+    pub frag_entry: &'static str,
+    pub polygon_mode: wgpu::PolygonMode,
+    pub cull_mode: Option<wgpu::Face>,
+    pub front_face: wgpu::FrontFace,
+    pub blend: wgpu::BlendState,
+    // Vertexes come with barycentric coordinates for vs_edges.
+    pub edges: bool,
+    // Wire frames drawn as a list of edges, where the adapter can't draw
+    // PolygonMode::Line.
+    pub line_list: bool,
+}
+
+pub struct Cache {
+    shader: wgpu::ShaderModule,
+    layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    pipelines: HashMap<Key, wgpu::RenderPipeline>,
+}
+
+impl Cache {
+    pub fn new(
+        shader: wgpu::ShaderModule,
+        layout: wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        Self { shader, layout, format, sample_count, pipelines: HashMap::new() }
+    }
+
+    pub fn get(&mut self, device: &wgpu::Device, key: Key) -> &wgpu::RenderPipeline {
+        self.pipelines.entry(key).or_insert_with(|| {
+            log::info!("Compiling pipeline for {:?}", key);
+            create(device, &self.shader, &self.layout, self.format, self.sample_count, key)
+        })
+    }
+}
+
+fn create(
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    key: Key,
+) -> wgpu::RenderPipeline {
+    let (vertex_entry, vertex_buffers) = if key.edges {
+        ("vs_edges", vec![vertex::Vertex::desc(), wireframe::desc()])
+    } else {
+        ("vs_main", vec![vertex::Vertex::desc()])
+    };
+    let (topology, polygon_mode) = if key.line_list {
+        (wgpu::PrimitiveTopology::LineList, wgpu::PolygonMode::Fill)
+    } else {
+        (wgpu::PrimitiveTopology::TriangleList, key.polygon_mode)
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: vertex_entry,
+            buffers: &vertex_buffers,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: key.frag_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(key.blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology,
+            strip_index_format: None,
+            front_face: key.front_face,
+            cull_mode: key.cull_mode,
+            // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
+            // or Features::POLYGON_MODE_POINT
+            polygon_mode,
+            // Requires Features::DEPTH_CLIP_CONTROL
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Depth::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        // If the pipeline will be used with a multiview render pass, this
+        // indicates how many array layers the attachments will have.
+        multiview: None,
+    })
+}
@@ -3,45 +3,70 @@
 struct CameraUniform {
     view_pos: vec4<f32>,
     view_proj: mat4x4<f32>,
+    model: mat4x4<f32>,
+    normal: mat4x4<f32>,
 };
 @group(1) @binding(0)
 var<uniform> camera: CameraUniform;
 
-struct GreyScaleUniform {
+// Values at the bottom and top of the color table
+struct ColorRangeUniform {
     low: f32,
     high: f32,
 }
-@group(2) @binding(0)
-var<uniform> greyscale: GreyScaleUniform;
+@group(3) @binding(0)
+var<uniform> color_range: ColorRangeUniform;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) scalar: f32,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
     @location(1) grey: f32,
+    @location(2) world_position: vec3<f32>,
+    @location(3) world_normal: vec3<f32>,
+    @location(4) barycentric: vec3<f32>,
 }
 
-@vertex
-fn vs_main(
-    model: VertexInput,
-) -> VertexOutput {
+fn vertex(model: VertexInput) -> VertexOutput {
     var out: VertexOutput;
-    let z: f32 = model.position.z;
-    let lo:f32 = -1.0;
-    let hi = 1.0;
-    // let grey: f32 = (clamp(z, grey.low, grey.high) - grey.low) /
-    //     (grey.high - grey.low);
-    out.grey = (clamp(z, lo, hi) - lo) / (hi - lo);
+    // No edges unless drawn with vs_edges
+    out.barycentric = vec3<f32>(1.0, 1.0, 1.0);
+    let lo = color_range.low;
+    let hi = color_range.high;
+    out.grey = (clamp(model.scalar, lo, hi) - lo) / (hi - lo);
     out.tex_coords = model.tex_coords;
     out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    let world = camera.model * vec4<f32>(model.position, 1.0);
+    out.world_position = world.xyz / world.w;
+    out.world_normal = (camera.normal * vec4<f32>(model.normal, 0.0)).xyz;
     // out.color = vec4<f32>(z, z, z, z);
     return out;
 }
 
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    return vertex(model);
+}
+
+// Unshared triangles with barycentric coordinates for the wire frame
+@vertex
+fn vs_edges(
+    model: VertexInput,
+    @location(4) barycentric: vec3<f32>,
+) -> VertexOutput {
+    var out = vertex(model);
+    out.barycentric = barycentric;
+    return out;
+}
+
 // Fragment shader
 
 // Wire frame texture
@@ -104,3 +129,60 @@ fn fs_wire(in: VertexOutput) -> @location(0) vec4<f32> {
     // return textureSample(ctab_tex, ctab_samp, in.grey);
 }
 
+// Lambert diffuse and Blinn-Phong specular over the color table. Both
+// sides are lit, since not every geometry winds its triangles the same way.
+const light_dir = vec3<f32>(-0.4, 0.6, 0.7);
+const ambient = 0.15;
+const shininess = 32.0;
+
+@fragment
+fn fs_lit(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color: vec4<f32> =
+        textureSample(ctab_tex, ctab_samp, in.grey);
+    let view_dir = normalize(camera.view_pos.xyz - in.world_position);
+    var normal = normalize(in.world_normal);
+    if dot(normal, view_dir) < 0.0 {
+        normal = -normal;
+    }
+    let light = normalize(light_dir);
+    let diffuse = max(dot(normal, light), 0.0);
+    let half_dir = normalize(light + view_dir);
+    let specular = pow(max(dot(normal, half_dir), 0.0), shininess);
+    let rgb = color.rgb * (ambient + diffuse) + vec3<f32>(0.3 * specular);
+    return vec4<f32>(rgb, color.a);
+}
+
+// Barycentric wire frame color and width in pixels
+struct EdgeUniform {
+    color: vec4<f32>,
+    width: f32,
+}
+@group(3) @binding(1)
+var<uniform> edge: EdgeUniform;
+
+// Coverage of the nearest edge, anti-aliased over about a pixel.
+// Dividing by the screen space rate of change gives the distance to each
+// edge in pixels. Left out edges have a constant 1 and stay far away.
+fn edge_coverage(barycentric: vec3<f32>) -> f32 {
+    let pixels = barycentric / max(fwidth(barycentric), vec3<f32>(1e-6));
+    let distance = min(pixels.x, min(pixels.y, pixels.z));
+    return clamp(edge.width / 2.0 + 0.5 - distance, 0.0, 1.0);
+}
+
+// Only barycentric wire frame
+@fragment
+fn fs_edges(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coverage = edge_coverage(in.barycentric);
+    if coverage <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(edge.color.rgb, coverage);
+}
+
+// Color with barycentric wire frame
+@fragment
+fn fs_colors_edges(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color: vec4<f32> =
+        textureSample(ctab_tex, ctab_samp, in.grey);
+    return mix(color, edge.color, edge_coverage(in.barycentric));
+}
@@ -1,9 +1,43 @@
 // Wireframe texture
-use std::num::NonZeroU32;
+use std::num::{NonZeroU8, NonZeroU32};
 
 use anyhow::*;
 use image::GenericImageView;
-use image::*;
+
+// How a texture is sampled. The default is trilinear over a mip chain.
+#[derive(Copy, Clone, Debug)]
+pub struct Sampling {
+    pub address_mode: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    // Most samples along the direction a texture is stretched, a power of
+    // 2 up to 16. Only used with all the filters linear.
+    pub anisotropy: u8,
+    // Whether 2D textures get a mip chain generated on upload.
+    pub mipmaps: bool,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self {
+            address_mode: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy: 1,
+            mipmaps: true,
+        }
+    }
+}
+
+impl Sampling {
+    fn anisotropy_clamp(&self) -> Option<NonZeroU8> {
+        let linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter().all(|&f| f == wgpu::FilterMode::Linear);
+        NonZeroU8::new(self.anisotropy).filter(|a| linear && a.get() > 1)
+    }
+}
 
 // Texture with a bind group
 pub struct Texture {
@@ -30,6 +64,27 @@ impl Texture {
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: &str,
+    ) -> Result<Self> {
+        // Color tables clamp so the top of the range doesn't wrap
+        // around to the bottom color.
+        let sampling = if img.height() == 1 {
+            Sampling {
+                address_mode: wgpu::AddressMode::ClampToEdge,
+                mipmaps: false,
+                ..Default::default()
+            }
+        } else {
+            Sampling::default()
+        };
+        Self::from_image_sampled(device, queue, img, label, &sampling)
+    }
+
+    pub fn from_image_sampled(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: &str,
+        sampling: &Sampling,
     ) -> Result<Self> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
@@ -59,11 +114,24 @@ impl Texture {
         //     usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         // });
 
+        // Each mip level halves the size down to 1 pixel. 1D textures
+        // can't have mip levels.
+        let mip_level_count = if sampling.mipmaps && dim == wgpu::TextureDimension::D2 {
+            32 - u32::max(dimensions.0, dimensions.1).leading_zeros()
+        } else {
+            1
+        };
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if mip_level_count > 1 {
+            // Mip levels are drawn from a copy of the level above.
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC;
+        }
+
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             // All textures are stored as 3D, we represent our 2D texture
             // by setting depth to 1.
             size,
-            mip_level_count: 1, // We'll talk about this a little later
+            mip_level_count,
             sample_count: 1,
             // dimension: wgpu::TextureDimension::D2,
             dimension: dim,
@@ -71,7 +139,7 @@ impl Texture {
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
             // TEXTURE_BINDING tells wgpu that we want to use this texture in shaders
             // COPY_DST means that we want to copy data to this texture
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            usage,
             label: Some(label),
             // This is the same as with the SurfaceConfig. It
             // specifies what texture formats can be used to
@@ -100,14 +168,19 @@ impl Texture {
             size,
         );
 
+        if mip_level_count > 1 {
+            generate_mipmaps(device, queue, &texture, size, mip_level_count);
+        }
+
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            address_mode_u: sampling.address_mode,
+            address_mode_v: sampling.address_mode,
+            address_mode_w: sampling.address_mode,
+            mag_filter: sampling.mag_filter,
+            min_filter: sampling.min_filter,
+            mipmap_filter: sampling.mipmap_filter,
+            anisotropy_clamp: sampling.anisotropy_clamp(),
             ..Default::default()
         });
 
@@ -165,6 +238,113 @@ impl Texture {
     }
 }
 
+// Fill mip levels 1 and up of a texture by drawing each from the one
+// above with a linear sampler, which averages each 2x2 block of texels.
+// Rgba8UnormSrgb is filtered in linear space, so this doesn't darken.
+// GL backends ignore the mip range of a sampled view, so each level is
+// first copied to a texture of its own to draw from.
+fn generate_mipmaps(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    size: wgpu::Extent3d,
+    mip_level_count: u32,
+) {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Blit Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("blit.wgsl").into()),
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Mipmap Pipeline"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::TextureFormat::Rgba8UnormSrgb.into())],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    });
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Mipmap Sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Mipmap Encoder"),
+    });
+    for level in 1..mip_level_count {
+        let source_size = size.mip_level_size(level - 1, wgpu::TextureDimension::D2);
+        let source = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Mipmap Source"),
+            size: source_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        encoder.copy_texture_to_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: level - 1,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            source.as_image_copy(),
+            source_size,
+        );
+        let source_view = source.create_view(&wgpu::TextureViewDescriptor::default());
+        let target_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Mip Level"),
+            base_mip_level: level,
+            mip_level_count: NonZeroU32::new(1),
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&source_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("Mipmap Bind Group"),
+        });
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Mipmap Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+}
+
 //  Depth texture.
 pub struct Depth {
     pub texture: wgpu::Texture,
@@ -179,6 +359,7 @@ impl Depth {
     pub fn create(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
         label: &str
     ) -> Self {
         let size = wgpu::Extent3d { // 2.
@@ -190,11 +371,16 @@ impl Depth {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT // 3.
-                | wgpu::TextureUsages::TEXTURE_BINDING,
+            // GL can't create multisampled depth textures that can be bound
+            usage: if sample_count > 1 {
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+            } else {
+                wgpu::TextureUsages::RENDER_ATTACHMENT // 3.
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+            },
             view_formats: &[],
         };
         let texture = device.create_texture(&desc);
@@ -219,3 +405,37 @@ impl Depth {
     }
 }
 
+
+// Multisampled color target, resolved into the surface texture at the end
+// of the render pass.
+pub struct Multisample {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl Multisample {
+    pub fn create(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        label: &str
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+}
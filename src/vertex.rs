@@ -4,6 +4,10 @@
 pub struct Vertex {
     pub position: [f32; 3],
     pub tex_coord: [f32; 2],
+    pub normal: [f32; 3],
+    // Value picked by --color-by and looked up in the color table,
+    // filled in by cli::Args::make.
+    pub scalar: f32,
 }
 
 impl Vertex {
@@ -23,11 +27,90 @@ impl Vertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32,
+                },
             ],
         }
     }
 }
 
+// Vertex normals from the area weighted average of the faces around each
+// vertex, for meshes without a better way to get them.
+pub fn compute_normals(vertexes: &mut [Vertex], indexes: &[u32]) {
+    use cgmath::{InnerSpace, Vector3, Zero};
+    let mut sums = vec![Vector3::<f32>::zero(); vertexes.len()];
+    for tri in indexes.chunks_exact(3) {
+        let [a, b, c] = [tri[0], tri[1], tri[2]]
+            .map(|i| Vector3::from(vertexes[i as usize].position));
+        let face = (b - a).cross(c - a);
+        for &i in tri {
+            sums[i as usize] += face;
+        }
+    }
+    for (vertex, sum) in vertexes.iter_mut().zip(sums) {
+        vertex.normal = if sum.magnitude2() > 0.0 {
+            sum.normalize().into()
+        } else {
+            [0.0, 0.0, 0.0]
+        };
+    }
+}
+
+// Smallest and largest coordinates over all the vertexes.
+pub fn bounds(vertexes: &[Vertex]) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    for v in vertexes {
+        for k in 0..3 {
+            min[k] = min[k].min(v.position[k]);
+            max[k] = max[k].max(v.position[k]);
+        }
+    }
+    (min, max)
+}
+
+// Stretch texture coordinates to 0..1 over the mesh, then tile them
+// scale times, so an image covers a generated surface whatever units its
+// generator counts texture coordinates in.
+pub fn fit_tex_coords(vertexes: &mut [Vertex], scale: (f32, f32)) {
+    let mut min = [f32::INFINITY; 2];
+    let mut max = [f32::NEG_INFINITY; 2];
+    for v in vertexes.iter() {
+        for k in 0..2 {
+            min[k] = min[k].min(v.tex_coord[k]);
+            max[k] = max[k].max(v.tex_coord[k]);
+        }
+    }
+    let scale = [scale.0, scale.1];
+    for v in vertexes.iter_mut() {
+        for k in 0..2 {
+            let span = max[k] - min[k];
+            let t = if span > 0.0 { (v.tex_coord[k] - min[k]) / span } else { 0.0 };
+            v.tex_coord[k] = t * scale[k];
+        }
+    }
+}
+
+// Index buffer contents and the format to draw them with. Indexes are
+// narrowed to 16 bits when they all fit, which halves the buffer size for
+// the small presets while larger meshes keep 32 bits.
+pub fn index_data(indexes: &[u32]) -> (Vec<u8>, wgpu::IndexFormat) {
+    if indexes.iter().all(|&i| i <= u16::MAX as u32) {
+        let narrow: Vec<u16> = indexes.iter().map(|&i| i as u16).collect();
+        (bytemuck::cast_slice(&narrow).to_vec(), wgpu::IndexFormat::Uint16)
+    } else {
+        (bytemuck::cast_slice(indexes).to_vec(), wgpu::IndexFormat::Uint32)
+    }
+}
+
 // let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
 //     label: Some("Vertex Buffer"),
 //     contents: bytemuck::cast_slice(&vertexes),
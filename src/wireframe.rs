@@ -0,0 +1,169 @@
+// Wire frames drawn in the fragment shader from barycentric coordinates,
+// so they follow the triangles whatever their texture coordinates and
+// keep the same width in pixels at any zoom.
+//
+// Every corner of a triangle needs its own coordinate, (1, 0, 0), (0, 1, 0)
+// or (0, 0, 1), so the triangles no longer share vertexes. An edge is left
+// out by giving the opposite corner's coordinate to all three corners, so
+// it never gets near 0 along that edge.
+use std::collections::{HashMap, HashSet};
+use crate::vertex::Vertex;
+
+// Each triangle with its own copy of its vertexes.
+pub fn unshare(vertexes: &[Vertex], indexes: &[u32]) -> Vec<Vertex> {
+    indexes.iter().map(|&i| vertexes[i as usize]).collect()
+}
+
+// Barycentric coordinates for the unshared corners. With quads, two
+// triangles whose longest edge is the same edge, like the halves of the
+// quads in grids, surfaces of revolution and OBJ quads, have that edge
+// left out wherever they are in the index list. Triangles without such
+// a partner, like most of a marching cubes mesh, keep all three edges.
+pub fn barycentric(vertexes: &[Vertex], indexes: &[u32], quads: bool) -> Vec<[f32; 3]> {
+    let triangles: Vec<&[u32]> = indexes.chunks_exact(3).collect();
+    // Corner opposite each triangle's left out edge
+    let mut opposite: Vec<Option<usize>> = vec![None; triangles.len()];
+    if quads {
+        let longest: Vec<usize> = triangles.iter()
+            .map(|tri| longest_edge(vertexes, tri))
+            .collect();
+        let mut claims: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+        for (t, tri) in triangles.iter().enumerate() {
+            let k = longest[t];
+            let (a, b) = (tri[(k + 1) % 3], tri[(k + 2) % 3]);
+            claims.entry((a.min(b), a.max(b))).or_default().push(t);
+        }
+        for claimants in claims.values() {
+            if let &[s, t] = &claimants[..] {
+                opposite[s] = Some(longest[s]);
+                opposite[t] = Some(longest[t]);
+            }
+        }
+    }
+    let mut coords = Vec::with_capacity(indexes.len());
+    for opposite in opposite {
+        let mut base = [0.0; 3];
+        if let Some(k) = opposite {
+            base[k] = 1.0;
+        }
+        for k in 0..3 {
+            let mut coord = base;
+            coord[k] = 1.0;
+            coords.push(coord);
+        }
+    }
+    coords
+}
+
+// The corner opposite a triangle's longest edge.
+fn longest_edge(vertexes: &[Vertex], tri: &[u32]) -> usize {
+    let length = |k: usize| {
+        let a = vertexes[tri[(k + 1) % 3] as usize].position;
+        let b = vertexes[tri[(k + 2) % 3] as usize].position;
+        (0..3).map(|i| (a[i] - b[i]).powi(2)).sum::<f32>()
+    };
+    (0..3).fold(0, |best, k| if length(k) > length(best) { k } else { best })
+}
+
+// Each edge of the triangles once, as pairs of indexes for a line list.
+// Used for wire frames where the adapter can't draw PolygonMode::Line.
+pub fn edge_indexes(indexes: &[u32]) -> Vec<u32> {
+    let mut seen: HashSet<(u32, u32)> = HashSet::new();
+    let mut edges = Vec::new();
+    for tri in indexes.chunks_exact(3) {
+        for k in 0..3 {
+            let (a, b) = (tri[k], tri[(k + 1) % 3]);
+            if seen.insert((a.min(b), a.max(b))) {
+                edges.extend([a, b]);
+            }
+        }
+    }
+    edges
+}
+
+pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &[wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 4,
+            format: wgpu::VertexFormat::Float32x3,
+        }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::grid::make_grid;
+
+    fn vertex(x: f32, y: f32) -> Vertex {
+        Vertex { position: [x, y, 0.0], tex_coord: [0.0; 2], normal: [0.0; 3], scalar: 0.0 }
+    }
+
+    // The edges a triangle's coordinates draw, as pairs of its corners.
+    fn drawn(coords: &[[f32; 3]]) -> Vec<(usize, usize)> {
+        (0..3).filter(|&k| coords.iter().any(|c| c[k] == 0.0))
+            .map(|k| ((k + 1) % 3, (k + 2) % 3))
+            .collect()
+    }
+
+    #[test]
+    fn unshare_copies_each_corner() {
+        let vertexes = [vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0)];
+        let unshared = unshare(&vertexes, &[0, 1, 2, 2, 1, 0]);
+        let positions: Vec<_> = unshared.iter().map(|v| v.position[..2].to_vec()).collect();
+        assert_eq!(positions, [
+            [0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [0.0, 1.0], [1.0, 0.0], [0.0, 0.0],
+        ]);
+    }
+
+    #[test]
+    fn all_edges_without_quads() {
+        let (vertexes, indexes) = make_grid(2, 2, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0, |_, _| 0.0);
+        let coords = barycentric(&vertexes, &indexes, false);
+        assert_eq!(coords.len(), indexes.len());
+        for tri in coords.chunks_exact(3) {
+            assert_eq!(tri, [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+        }
+    }
+
+    #[test]
+    fn quads_leave_out_grid_diagonals() {
+        let (vertexes, indexes) = make_grid(3, 2, -1.0, -1.0, 1.0, 1.0, 1.0, 1.0, |_, _| 0.0);
+        // Pairs are found by edge, not by order.
+        let mut triangles: Vec<&[u32]> = indexes.chunks_exact(3).collect();
+        triangles.reverse();
+        triangles.swap(0, 3);
+        let indexes = triangles.concat();
+        let coords = barycentric(&vertexes, &indexes, true);
+        for (tri, coords) in indexes.chunks_exact(3).zip(coords.chunks_exact(3)) {
+            let drawn = drawn(coords);
+            assert_eq!(drawn.len(), 2);
+            // What's drawn runs along the grid, which leaves the diagonal.
+            for (a, b) in drawn {
+                let (p, q) = (vertexes[tri[a] as usize].position, vertexes[tri[b] as usize].position);
+                assert!(p[0] == q[0] || p[1] == q[1], "{:?} to {:?}", p, q);
+            }
+        }
+    }
+
+    #[test]
+    fn quads_keep_edges_that_arent_longest() {
+        // Two triangles sharing their shortest edge, as in a polygon fan
+        let vertexes = [vertex(0.0, 0.0), vertex(0.0, 1.0), vertex(3.0, 0.0), vertex(-3.0, 0.0)];
+        let coords = barycentric(&vertexes, &[0, 1, 2, 1, 0, 3], true);
+        assert_eq!(drawn(&coords[..3]).len(), 3);
+        assert_eq!(drawn(&coords[3..]).len(), 3);
+    }
+
+    #[test]
+    fn edge_indexes_lists_each_edge_once() {
+        let edges = edge_indexes(&[0, 1, 2, 2, 1, 3]);
+        let pairs: Vec<_> = edges.chunks_exact(2).map(|e| (e[0], e[1])).collect();
+        // 1-2 is shared, and kept the way the first triangle has it.
+        assert_eq!(pairs, [(0, 1), (1, 2), (2, 0), (1, 3), (3, 2)]);
+        assert!(edge_indexes(&[]).is_empty());
+    }
+}